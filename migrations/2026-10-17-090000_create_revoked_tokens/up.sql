@@ -0,0 +1,12 @@
+-- A row with a jti revokes that single token. A row without a jti revokes every token
+-- issued to the account before revoked_at (logout everywhere, bans, password changes).
+CREATE TABLE IF NOT EXISTS revoked_tokens (
+    id SERIAL PRIMARY KEY,
+    account_id INT NOT NULL REFERENCES accounts(id),
+    jti VARCHAR(64) UNIQUE,
+    reason VARCHAR(100),
+    expires_at TIMESTAMP WITH TIME ZONE,
+    revoked_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_revoked_tokens_account_id ON revoked_tokens(account_id);
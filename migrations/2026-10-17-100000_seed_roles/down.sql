@@ -0,0 +1,4 @@
+DELETE FROM account_roles
+WHERE role_id IN (SELECT id FROM roles WHERE code IN ('player', 'vip', 'support', 'admin'));
+
+DELETE FROM roles WHERE code IN ('player', 'vip', 'support', 'admin');
@@ -0,0 +1,6 @@
+INSERT INTO roles (code, name, description) VALUES
+    ('player', 'Player', 'Regular player with access to standard tables'),
+    ('vip', 'VIP', 'Player with access to private rooms and high-limit tables'),
+    ('support', 'Support', 'Support staff with read access to player accounts'),
+    ('admin', 'Administrator', 'Full access including admin commands')
+ON CONFLICT (code) DO NOTHING;
@@ -0,0 +1,5 @@
+-- An account is locked while is_active is false and locked_until is set. Accounts that are
+-- deactivated without a locked_until (bans) stay inactive until an admin intervenes.
+ALTER TABLE accounts
+    ADD COLUMN failed_login_attempts INT NOT NULL DEFAULT 0,
+    ADD COLUMN locked_until TIMESTAMP WITH TIME ZONE;
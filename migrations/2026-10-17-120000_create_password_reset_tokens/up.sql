@@ -0,0 +1,13 @@
+-- Reset tokens are handed out as "<selector>.<verifier>". The selector finds the row, the
+-- verifier is only stored as an argon2 hash so a leaked table cannot be used to reset passwords.
+CREATE TABLE IF NOT EXISTS password_reset_tokens (
+    id SERIAL PRIMARY KEY,
+    account_id INT NOT NULL REFERENCES accounts(id),
+    selector VARCHAR(32) NOT NULL UNIQUE,
+    verifier_hash VARCHAR(255) NOT NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    used_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_password_reset_tokens_account_id ON password_reset_tokens(account_id);
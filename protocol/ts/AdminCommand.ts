@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Role } from "./Role";
+
+export type AdminCommand = { "type": "ListRoles", "data": { account_id: number, } } | { "type": "GrantRole", "data": { account_id: number, role: Role, } } | { "type": "RevokeRole", "data": { account_id: number, role: Role, } } | { "type": "BanAccount", "data": { account_id: number, } } | { "type": "ListLockouts" } | { "type": "UnlockAccount", "data": { account_id: number, } } | { "type": "ListSessions", "data": { account_id: number, } } | { "type": "EndSession", "data": { session_id: string, } } | { "type": "ConnectionStats" };
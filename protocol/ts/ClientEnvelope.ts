@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AdminCommand } from "./AdminCommand";
+import type { JsonValue } from "./serde_json/JsonValue";
+
+/**
+ * A client message with an optional id of the client's choosing, e.g.
+ * `{"request_id": "42", "type": "GetBalance"}`. The server tags its direct reply with the
+ * same id so that clients with several requests in flight can match them up.
+ */
+export type ClientEnvelope = { request_id?: string, } & ({ "type": "Hello", "data": { protocol_version: number, client_name?: string, capabilities?: Array<string>, } } | { "type": "Auth", "data": { username: string, password: string, } } | { "type": "Register", "data": { username: string, email: string, password: string, first_name: string, last_name: string, citizen_id: string, } } | { "type": "GuestLogin" } | { "type": "UpgradeGuest", "data": { username: string, email: string, password: string, first_name: string, last_name: string, citizen_id: string, } } | { "type": "GetBalance" } | { "type": "RefreshToken", "data": { refresh_token: string, } } | { "type": "Logout", "data": { refresh_token?: string, all_devices?: boolean, } } | { "type": "Resume", "data": { token: string, last_seq: number, } } | { "type": "ChangePassword", "data": { current_password: string, new_password: string, } } | { "type": "RequestPasswordReset", "data": { email: string, } } | { "type": "ResetPassword", "data": { token: string, new_password: string, } } | { "type": "ListSessions" } | { "type": "EndSession", "data": { session_id: string, } } | { "type": "SelectGame", "data": { game_type: string, high_limit?: boolean, free_play?: boolean, } } | { "type": "CreatePrivateRoom", "data": { game_type: string, } } | { "type": "GameAction", "data": { action: string, params?: JsonValue, } } | { "type": "Admin", "data": { command: AdminCommand, } } | { "type": "Quit" });
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * What went wrong, for clients to react to in code. The `message` of an `Error` is English
+ * text meant for logs; clients word errors themselves, using the error's `details`.
+ */
+export type ErrorCode = "invalid_message" | "unexpected_message" | "unauthorized" | "unknown_session" | "invalid_game_type" | "room_not_found" | "not_in_room" | "invalid_action" | "invalid_params" | "not_your_turn" | "insufficient_funds" | "balance_unavailable" | "rate_limited" | "admin_command_failed";
@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { PokerEvent } from "./PokerEvent";
+import type { RouletteEvent } from "./RouletteEvent";
+
+/**
+ * What a room made of an action, pushed to the player as the state of a game update.
+ */
+export type GameEvent = RouletteEvent | PokerEvent;
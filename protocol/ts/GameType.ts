@@ -0,0 +1,14 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AsianGameVariant } from "./AsianGameVariant";
+import type { CardGameVariant } from "./CardGameVariant";
+import type { DiceVariant } from "./DiceVariant";
+import type { LotteryVariant } from "./LotteryVariant";
+import type { OtherGameVariant } from "./OtherGameVariant";
+import type { PokerVariant } from "./PokerVariant";
+import type { RacingVariant } from "./RacingVariant";
+import type { RouletteVariant } from "./RouletteVariant";
+import type { SiciwinGameVariant } from "./SiciwinGameVariant";
+import type { SlotsVariant } from "./SlotsVariant";
+import type { TableGameVariant } from "./TableGameVariant";
+
+export type GameType = { "Roulette": RouletteVariant } | { "CardGame": CardGameVariant } | { "Poker": PokerVariant } | { "Slots": SlotsVariant } | { "Lottery": LotteryVariant } | { "Racing": RacingVariant } | { "Dice": DiceVariant } | { "TableGame": TableGameVariant } | { "AsianGame": AsianGameVariant } | { "SiciwinGame": SiciwinGameVariant } | { "Other": OtherGameVariant };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Actions that are restricted to some roles.
+ */
+export type Permission = "JoinHighLimitTable" | "CreatePrivateRoom" | "ViewAccounts" | "ManageAccounts";
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type PokerAction = { "action": "fold" } | { "action": "check" } | { "action": "call" } | { "action": "bet", "params": { amount: string, } } | { "action": "raise", "params": { amount: string, } } | { "action": "all_in" };
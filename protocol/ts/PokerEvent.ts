@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type PokerEvent = { "event": "folded", player_id: string, } | { "event": "checked", player_id: string, } | { "event": "called", player_id: string, } | { "event": "bet_placed", player_id: string, amount: string, } | { "event": "raised", player_id: string, amount: string, } | { "event": "went_all_in", player_id: string, };
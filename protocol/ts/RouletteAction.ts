@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { RouletteBet } from "./RouletteBet";
+
+export type RouletteAction = { "action": "place_bet", "params": { bet: RouletteBet, amount: string, } } | { "action": "clear_bets" } | { "action": "spin" };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A roulette bet, e.g. `"red"` or `{"straight": 17}`.
+ */
+export type RouletteBet = { "straight": number } | "double_zero" | "red" | "black" | "odd" | "even" | "low" | "high" | { "dozen": number } | { "column": number };
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { RouletteBet } from "./RouletteBet";
+
+export type RouletteEvent = { "event": "bet_placed", player_id: string, bet: RouletteBet, amount: string, } | { "event": "bets_cleared", player_id: string, } | { "event": "spin_requested", player_id: string, };
@@ -0,0 +1,22 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ErrorCode } from "./ErrorCode";
+import type { Permission } from "./Permission";
+import type { SessionInfo } from "./SessionInfo";
+import type { JsonValue } from "./serde_json/JsonValue";
+
+/**
+ * A server message as it goes out. Direct replies carry the `request_id` of the client
+ * message they answer, if it had one. Messages the server sends on its own, like game
+ * updates or the shutdown notice, carry `seq` instead: a number that grows by one with every
+ * such message on the connection, so clients can tell if one went missing. It is not the
+ * `seq` of a `GameUpdate`, which counts the updates of a room session across reconnects.
+ */
+export type ServerEnvelope = { request_id?: string, seq?: number, } & ({ "type": "Welcome", "data": { protocol_version: number, server_version: string, game_types: Array<string>, features: Array<string>, } } | { "type": "ProtocolRejected", "data": { reason: string, min_version: number, max_version: number, } } | { "type": "AuthSuccess", "data": { token: string, refresh_token: string, expires_in: number, } } | { "type": "AuthFailed" } | { "type": "AccountLocked", "data": { locked_until: number, } } | { "type": "LoginThrottled", "data": { retry_after_secs: number, } } | { "type": "RegisterSuccess", "data": { account_id: number, } } | { "type": "RegisterFailed", "data": { reason: string, 
+/**
+ * The registration field that was turned down, e.g. `email`.
+ */
+field: string | null, 
+/**
+ * Whether `field` is already registered to another account, as opposed to invalid.
+ */
+taken: boolean, } } | { "type": "GuestSession", "data": { token: string, player_id: string, display_name: string, expires_in: number, balance: string, } } | { "type": "GuestUpgraded", "data": { account_id: number, token: string, refresh_token: string, expires_in: number, } } | { "type": "Balance", "data": { balance: string, play_money: boolean, } } | { "type": "LoggedOut" } | { "type": "PasswordChanged" } | { "type": "PasswordResetRequested" } | { "type": "PasswordChangeFailed", "data": { reason: string, } } | { "type": "Sessions", "data": { current_session_id: string, sessions: Array<SessionInfo>, } } | { "type": "SessionClosed", "data": { session_id: string, } } | { "type": "SessionEnded", "data": { reason: string, } } | { "type": "SessionLimitReached", "data": { max_sessions: number, } } | { "type": "ServerShutdown", "data": { timeout_secs: number, } } | { "type": "GameAssigned", "data": { room_id: string, game_type: string, } } | { "type": "Resumed", "data": { room_id: string, last_seq: number, history_truncated: boolean, } } | { "type": "ResumeFailed", "data": { reason: string, } } | { "type": "GameUpdate", "data": { seq: number, state: JsonValue, } } | { "type": "PermissionDenied", "data": { permission: Permission, reason: string, } } | { "type": "AdminResult", "data": { result: JsonValue, } } | { "type": "Error", "data": { code: ErrorCode, message: string, details?: JsonValue, } } | { "type": "Echo", "data": { message: JsonValue, } });
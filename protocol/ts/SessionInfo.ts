@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A socket of an account, as shown to the player and to admins.
+ */
+export type SessionInfo = { session_id: string, device: string | null, ip: string, connected_since: string, room_id: string | null, };
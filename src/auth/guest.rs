@@ -0,0 +1,81 @@
+use chrono::Utc;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+struct Guest {
+    balance: Decimal,
+    expires_at: i64,
+}
+
+/// Guests that are currently allowed to play, with their play-money balance.
+///
+/// Guests only live in memory: their tokens stop working when the server restarts or the
+/// guest is upgraded to an account, and play money never touches the real-money tables.
+#[derive(Default)]
+pub struct GuestStore {
+    guests: Mutex<HashMap<String, Guest>>,
+}
+
+impl GuestStore {
+    pub fn insert(&self, player_id: &str, balance: Decimal, expires_at: i64) {
+        let mut guests = self.guests.lock().unwrap();
+        let now = Utc::now().timestamp();
+        guests.retain(|_, guest| guest.expires_at > now);
+        guests.insert(
+            player_id.to_string(),
+            Guest {
+                balance,
+                expires_at,
+            },
+        );
+    }
+
+    pub fn contains(&self, player_id: &str) -> bool {
+        self.guests.lock().unwrap().contains_key(player_id)
+    }
+
+    pub fn balance(&self, player_id: &str) -> Option<Decimal> {
+        self.guests
+            .lock()
+            .unwrap()
+            .get(player_id)
+            .map(|guest| guest.balance)
+    }
+
+    pub fn remove(&self, player_id: &str) {
+        self.guests.lock().unwrap().remove(player_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_balance_of_a_guest_until_removed() {
+        let guests = GuestStore::default();
+        let expires_at = Utc::now().timestamp() + 60;
+        guests.insert("guest-a", Decimal::from(1000), expires_at);
+
+        assert!(guests.contains("guest-a"));
+        assert_eq!(guests.balance("guest-a"), Some(Decimal::from(1000)));
+
+        guests.remove("guest-a");
+        assert!(!guests.contains("guest-a"));
+        assert_eq!(guests.balance("guest-a"), None);
+    }
+
+    #[test]
+    fn drops_expired_guests_when_a_new_one_arrives() {
+        let guests = GuestStore::default();
+        let now = Utc::now().timestamp();
+        guests.insert("guest-old", Decimal::from(1000), now - 1);
+        guests.insert("guest-live", Decimal::from(1000), now + 60);
+        guests.insert("guest-new", Decimal::from(500), now + 60);
+
+        assert!(!guests.contains("guest-old"));
+        assert!(guests.contains("guest-live"));
+        assert_eq!(guests.balance("guest-new"), Some(Decimal::from(500)));
+    }
+}
@@ -0,0 +1,104 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::sql_types::Bool;
+use diesel::PgConnection;
+use serde::Serialize;
+
+use crate::schema::accounts;
+
+/// An account that is locked after too many failed logins.
+#[derive(Queryable, Serialize)]
+pub struct LockedAccount {
+    pub account_id: i32,
+    pub username: String,
+    pub failed_login_attempts: i32,
+    pub locked_until: Option<NaiveDateTime>,
+}
+
+/// Counts a failed login against the account and locks it for `lockout_secs` once
+/// `max_attempts` consecutive failures are reached. Returns the unlock time if the account
+/// got locked. Accounts deactivated for another reason are left alone.
+pub fn record_failed_login(
+    conn: &mut PgConnection,
+    account_id: i32,
+    max_attempts: i32,
+    lockout_secs: i64,
+) -> QueryResult<Option<NaiveDateTime>> {
+    conn.transaction(|conn| {
+        let attempts = diesel::update(accounts::table.find(account_id))
+            .filter(lockable())
+            .set(accounts::failed_login_attempts.eq(accounts::failed_login_attempts + 1))
+            .returning(accounts::failed_login_attempts)
+            .get_result::<i32>(conn)
+            .optional()?;
+
+        match attempts {
+            Some(attempts) if attempts >= max_attempts => {}
+            _ => return Ok(None),
+        }
+
+        let now = Utc::now().naive_utc();
+        let locked_until = now + Duration::seconds(lockout_secs);
+        diesel::update(accounts::table.find(account_id))
+            .set((
+                accounts::is_active.eq(Some(false)),
+                accounts::locked_until.eq(Some(locked_until)),
+                accounts::updated_at.eq(now),
+            ))
+            .execute(conn)?;
+        Ok(Some(locked_until))
+    })
+}
+
+/// Resets the failure counter and records the login time.
+pub fn record_successful_login(conn: &mut PgConnection, account_id: i32) -> QueryResult<()> {
+    diesel::update(accounts::table.find(account_id))
+        .set((
+            accounts::failed_login_attempts.eq(0),
+            accounts::last_login.eq(Utc::now().naive_utc()),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Lifts a lockout and returns the account's username. Accounts that were deactivated for
+/// another reason, e.g. a ban, are left alone and `None` is returned.
+pub fn unlock_account(conn: &mut PgConnection, account_id: i32) -> QueryResult<Option<String>> {
+    diesel::update(accounts::table.find(account_id))
+        .filter(accounts::locked_until.is_not_null())
+        .filter(lockable())
+        .set((
+            accounts::is_active.eq(Some(true)),
+            accounts::locked_until.eq(None::<NaiveDateTime>),
+            accounts::failed_login_attempts.eq(0),
+            accounts::updated_at.eq(Utc::now().naive_utc()),
+        ))
+        .returning(accounts::username)
+        .get_result(conn)
+        .optional()
+}
+
+/// Accounts that are either active or deactivated by a lockout, as opposed to e.g. banned.
+fn lockable() -> Box<dyn BoxableExpression<accounts::table, Pg, SqlType = Bool>> {
+    Box::new(
+        accounts::is_active
+            .is_distinct_from(false)
+            .or(accounts::locked_until.is_not_null()),
+    )
+}
+
+/// Accounts whose lockout has not expired yet.
+pub fn locked_accounts(conn: &mut PgConnection) -> QueryResult<Vec<LockedAccount>> {
+    accounts::table
+        .filter(accounts::locked_until.gt(Utc::now().naive_utc()))
+        .filter(accounts::deleted_at.is_null())
+        .order(accounts::locked_until.asc())
+        .select((
+            accounts::id,
+            accounts::username,
+            accounts::failed_login_attempts,
+            accounts::locked_until,
+        ))
+        .load(conn)
+}
@@ -0,0 +1,789 @@
+mod guest;
+mod lockout;
+mod password;
+pub mod policy;
+mod registration;
+mod reset;
+mod revocation;
+mod roles;
+mod throttle;
+mod token;
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::db::{DbError, DbPool};
+use crate::models::{Account, NewAccount, NewUser, UpdateAccount, User};
+use crate::notifier::{Notification, Notifier, NotifierError};
+use crate::queues::db_queue::{DbOperation, DbQueue};
+use crate::schema::{accounts, users};
+
+pub use lockout::LockedAccount;
+pub use registration::Registration;
+pub use roles::Role;
+pub use throttle::ThrottleEntry;
+pub use token::{Claims, TokenKind};
+
+use guest::GuestStore;
+use password::{hash_password, verify_dummy_password, verify_password};
+use registration::{map_registration_error, validate_password};
+use throttle::LoginThrottle;
+
+#[derive(Error, Debug)]
+pub enum AuthError {
+    #[error("Invalid username or password")]
+    InvalidCredentials,
+    #[error("Account is not active")]
+    AccountInactive,
+    #[error("Account is locked after too many failed logins")]
+    AccountLocked { locked_until: NaiveDateTime },
+    #[error("Too many failed logins, retry in {retry_after_secs}s")]
+    LoginThrottled { retry_after_secs: u64 },
+    #[error("Username is already taken")]
+    UsernameTaken,
+    #[error("Email is already registered")]
+    EmailTaken,
+    #[error("Citizen id is already registered")]
+    CitizenIdTaken,
+    #[error("Invalid {field}: {reason}")]
+    InvalidRegistration {
+        field: &'static str,
+        reason: &'static str,
+    },
+    #[error("Invalid token")]
+    InvalidToken,
+    #[error("Token has been revoked")]
+    TokenRevoked,
+    #[error("Invalid or expired reset token")]
+    InvalidResetToken,
+    #[error("Missing JWT configuration: {0}")]
+    MissingConfig(&'static str),
+    #[error("Password hashing error: {0}")]
+    PasswordHash(String),
+    #[error("Token error: {0}")]
+    Token(#[from] jsonwebtoken::errors::Error),
+    #[error(transparent)]
+    Db(#[from] DbError),
+    #[error(transparent)]
+    Notifier(#[from] NotifierError),
+    #[error("Blocking task failed: {0}")]
+    Blocking(#[from] tokio::task::JoinError),
+}
+
+/// A short-lived access token and the long-lived refresh token issued alongside it.
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+    /// Claims of the access token.
+    pub claims: Claims,
+    pub refresh_claims: Claims,
+    /// Lifetime of the access token in seconds.
+    pub expires_in: i64,
+}
+
+/// A temporary identity for a visitor without an account.
+pub struct GuestSession {
+    pub access_token: String,
+    pub claims: Claims,
+    pub expires_in: i64,
+    /// Play-money balance the guest starts with.
+    pub balance: Decimal,
+}
+
+/// Accounts locked after too many failed logins, and the usernames and addresses that are
+/// currently backing off.
+#[derive(Serialize)]
+pub struct Lockouts {
+    pub accounts: Vec<LockedAccount>,
+    pub throttled: Vec<ThrottleEntry>,
+}
+
+pub struct AuthService {
+    db_pool: Arc<DbPool>,
+    db_queue: Arc<DbQueue>,
+    notifier: Arc<dyn Notifier>,
+    jwt_secret: String,
+    jwt_issuer: String,
+    access_ttl_secs: i64,
+    refresh_ttl_secs: i64,
+    max_failed_logins: i32,
+    lockout_secs: i64,
+    password_reset_ttl_secs: i64,
+    guest_ttl_secs: i64,
+    guest_starting_balance: Decimal,
+    throttle: LoginThrottle,
+    guests: GuestStore,
+}
+
+impl AuthService {
+    pub fn new(
+        db_pool: Arc<DbPool>,
+        db_queue: Arc<DbQueue>,
+        notifier: Arc<dyn Notifier>,
+        config: &Config,
+    ) -> Result<Self, AuthError> {
+        let jwt_secret = config
+            .jwt_secret
+            .clone()
+            .ok_or(AuthError::MissingConfig("jwt secret"))?;
+        let jwt_issuer = config
+            .jwt_issuer
+            .clone()
+            .ok_or(AuthError::MissingConfig("jwt issuer"))?;
+
+        Ok(AuthService {
+            db_pool,
+            db_queue,
+            notifier,
+            jwt_secret,
+            jwt_issuer,
+            access_ttl_secs: config.jwt_access_ttl_secs,
+            refresh_ttl_secs: config.jwt_refresh_ttl_secs,
+            max_failed_logins: config.max_failed_logins,
+            lockout_secs: config.lockout_secs,
+            password_reset_ttl_secs: config.password_reset_ttl_secs,
+            guest_ttl_secs: config.guest_ttl_secs,
+            guest_starting_balance: config.guest_starting_balance,
+            throttle: LoginThrottle::new(
+                Duration::from_millis(config.login_backoff_base_ms),
+                Duration::from_secs(config.login_backoff_max_secs),
+            ),
+            guests: GuestStore::default(),
+        })
+    }
+
+    /// Checks a username and password against the `accounts` table and issues an access
+    /// and refresh token for the matching account.
+    ///
+    /// Failed attempts are throttled per username and per client address with exponential
+    /// backoff, and an account is locked for a while after too many consecutive failures.
+    ///
+    /// ### Errors
+    ///
+    /// Returns `AuthError::InvalidCredentials` if the account does not exist or the password
+    /// does not match, `AuthError::LoginThrottled` while the username or address is backing
+    /// off, `AuthError::AccountLocked` while the account is locked and
+    /// `AuthError::AccountInactive` if the account has been deactivated.
+    pub async fn login(
+        self: &Arc<Self>,
+        username: &str,
+        password: &str,
+        ip: IpAddr,
+    ) -> Result<TokenPair, AuthError> {
+        let (username, password) = (username.to_string(), password.to_string());
+        self.run_blocking(move |auth| auth.login_blocking(&username, &password, ip))
+            .await
+    }
+
+    fn login_blocking(
+        &self,
+        username: &str,
+        password: &str,
+        ip: IpAddr,
+    ) -> Result<TokenPair, AuthError> {
+        if let Some(wait) = self.throttle.check(username, ip) {
+            return Err(AuthError::LoginThrottled {
+                retry_after_secs: wait.as_secs_f64().ceil() as u64,
+            });
+        }
+
+        let mut conn = self.db_pool.pool.get().map_err(DbError::from)?;
+        let result = self.check_credentials(&mut conn, username, password);
+        match &result {
+            Ok(_) => self.throttle.clear(username),
+            Err(AuthError::InvalidCredentials | AuthError::AccountLocked { .. }) => {
+                self.throttle.record_failure(username, ip)
+            }
+            Err(_) => {}
+        }
+        let account = result?;
+
+        lockout::record_successful_login(&mut conn, account.id).map_err(DbError::from)?;
+        self.issue_tokens(&account)
+    }
+
+    fn check_credentials(
+        &self,
+        conn: &mut PgConnection,
+        username: &str,
+        password: &str,
+    ) -> Result<Account, AuthError> {
+        let account = accounts::table
+            .filter(accounts::username.eq(username))
+            .filter(accounts::deleted_at.is_null())
+            .first::<Account>(conn)
+            .optional()
+            .map_err(DbError::from)?;
+        let Some(mut account) = account else {
+            verify_dummy_password(password);
+            return Err(AuthError::InvalidCredentials);
+        };
+
+        match (account.is_active, account.locked_until) {
+            // Deactivated without a lockout, e.g. banned: failed logins must not lock the
+            // account, or the lockout expiring would reactivate it
+            (Some(false), None) => return Err(AuthError::AccountInactive),
+            (Some(false), Some(locked_until)) => {
+                if locked_until > Utc::now().naive_utc() {
+                    return Err(AuthError::AccountLocked { locked_until });
+                }
+                lockout::unlock_account(conn, account.id).map_err(DbError::from)?;
+                account.is_active = Some(true);
+            }
+            _ => {}
+        }
+
+        if !verify_password(password, &account.password)? {
+            let locked = lockout::record_failed_login(
+                conn,
+                account.id,
+                self.max_failed_logins,
+                self.lockout_secs,
+            )
+            .map_err(DbError::from)?;
+
+            return Err(match locked {
+                Some(locked_until) => AuthError::AccountLocked { locked_until },
+                None => AuthError::InvalidCredentials,
+            });
+        }
+
+        Ok(account)
+    }
+
+    /// Creates a `users` row and its `accounts` row in a single transaction and announces
+    /// the new player on the db queue.
+    ///
+    /// ### Errors
+    ///
+    /// Returns `AuthError::InvalidRegistration` if the details fail validation, and
+    /// `AuthError::UsernameTaken`, `AuthError::EmailTaken` or `AuthError::CitizenIdTaken`
+    /// if the corresponding unique constraint is violated.
+    pub async fn register(
+        self: &Arc<Self>,
+        registration: Registration,
+    ) -> Result<Account, AuthError> {
+        registration.validate()?;
+
+        let (account, password_hash) = self
+            .run_blocking(move |auth| auth.create_account_blocking(&registration))
+            .await?;
+
+        let operation = DbOperation::CreatePlayer {
+            id: account.id.to_string(),
+            username: account.username.clone(),
+            password_hash,
+        };
+        if let Err(e) = self.db_queue.publish(operation).await {
+            error!(
+                "Failed to publish CreatePlayer for {}: {}",
+                account.username, e
+            );
+        }
+
+        Ok(account)
+    }
+
+    /// Hashes the password and inserts the account. Returns the account and its password
+    /// hash.
+    fn create_account_blocking(
+        &self,
+        registration: &Registration,
+    ) -> Result<(Account, String), AuthError> {
+        let password_hash = hash_password(&registration.password)?;
+        let mut conn = self.db_pool.pool.get().map_err(DbError::from)?;
+
+        let account = conn
+            .transaction::<_, diesel::result::Error, _>(|conn| {
+                let user = diesel::insert_into(users::table)
+                    .values(&NewUser {
+                        first_name: registration.first_name.trim().to_string(),
+                        last_name: registration.last_name.trim().to_string(),
+                        citizen_id: registration.citizen_id.trim().to_string(),
+                    })
+                    .get_result::<User>(conn)?;
+
+                let account = diesel::insert_into(accounts::table)
+                    .values(&NewAccount {
+                        user_id: user.id,
+                        email: registration.email.clone(),
+                        username: registration.username.clone(),
+                        password: password_hash.clone(),
+                        is_active: Some(true),
+                    })
+                    .get_result::<Account>(conn)?;
+
+                roles::grant_role(conn, account.id, Role::Player)?;
+                Ok(account)
+            })
+            .map_err(map_registration_error)?;
+
+        Ok((account, password_hash))
+    }
+
+    /// Issues an access token for a new guest. Guests get no refresh token; once the token
+    /// expires they start over as a new guest.
+    pub fn guest_login(&self) -> Result<GuestSession, AuthError> {
+        let id = Uuid::new_v4().simple().to_string();
+        let now = Utc::now().timestamp();
+        let claims = Claims {
+            sub: format!("guest-{}", id),
+            username: format!("Guest-{}", &id[..6]),
+            iss: self.jwt_issuer.clone(),
+            iat: now,
+            exp: now + self.guest_ttl_secs,
+            jti: Uuid::new_v4().to_string(),
+            typ: TokenKind::Access,
+            roles: Vec::new(),
+            guest: true,
+        };
+
+        let access_token = token::encode_token(&claims, &self.jwt_secret)?;
+        self.guests
+            .insert(&claims.sub, self.guest_starting_balance, claims.exp);
+
+        Ok(GuestSession {
+            access_token,
+            claims,
+            expires_in: self.guest_ttl_secs,
+            balance: self.guest_starting_balance,
+        })
+    }
+
+    /// Play-money balance of a guest.
+    pub fn guest_balance(&self, claims: &Claims) -> Option<Decimal> {
+        self.guests.balance(&claims.sub)
+    }
+
+    /// Registers an account for a guest and logs it in. The guest identity and its play
+    /// money are discarded.
+    pub async fn upgrade_guest(
+        self: &Arc<Self>,
+        guest: &Claims,
+        registration: Registration,
+    ) -> Result<(Account, TokenPair), AuthError> {
+        if !guest.guest || !self.guests.contains(&guest.sub) {
+            return Err(AuthError::InvalidToken);
+        }
+
+        let account = self.register(registration).await?;
+        self.guests.remove(&guest.sub);
+        self.run_blocking(move |auth| {
+            let tokens = auth.issue_tokens(&account)?;
+            Ok((account, tokens))
+        })
+        .await
+    }
+
+    /// Replaces the password of an account after checking its current one. All tokens of the
+    /// account are revoked, so every session has to log in again with the new password.
+    pub async fn change_password(
+        self: &Arc<Self>,
+        account_id: i32,
+        current_password: &str,
+        new_password: &str,
+    ) -> Result<(), AuthError> {
+        let (current_password, new_password) =
+            (current_password.to_string(), new_password.to_string());
+        self.run_blocking(move |auth| {
+            auth.change_password_blocking(account_id, &current_password, &new_password)
+        })
+        .await
+    }
+
+    fn change_password_blocking(
+        &self,
+        account_id: i32,
+        current_password: &str,
+        new_password: &str,
+    ) -> Result<(), AuthError> {
+        let mut conn = self.db_pool.pool.get().map_err(DbError::from)?;
+        let account = accounts::table
+            .find(account_id)
+            .filter(accounts::deleted_at.is_null())
+            .first::<Account>(&mut conn)
+            .optional()
+            .map_err(DbError::from)?
+            .ok_or(AuthError::InvalidCredentials)?;
+
+        if !verify_password(current_password, &account.password)? {
+            return Err(AuthError::InvalidCredentials);
+        }
+        validate_password(new_password)?;
+
+        let password_hash = hash_password(new_password)?;
+        set_password(&mut conn, account_id, password_hash, "password_changed")
+    }
+
+    /// Sends a one-time password reset token to the account registered with the email.
+    /// Unknown emails are ignored so that callers cannot probe which emails are registered.
+    /// The token is created and sent in the background, so the caller returns just as fast
+    /// for either.
+    pub fn request_password_reset(self: &Arc<Self>, email: &str) {
+        let auth = Arc::clone(self);
+        let email = email.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = auth.send_password_reset(&email).await {
+                error!("Error requesting password reset: {}", e);
+            }
+        });
+    }
+
+    async fn send_password_reset(self: &Arc<Self>, email: &str) -> Result<(), AuthError> {
+        let email = email.to_string();
+        let Some((account, token)) = self
+            .run_blocking(move |auth| auth.create_reset_token_blocking(&email))
+            .await?
+        else {
+            return Ok(());
+        };
+
+        self.notifier
+            .notify(Notification {
+                recipient: account.email,
+                subject: "Password reset".to_string(),
+                body: format!(
+                    "Hi {}, use this token to reset your password within {} minutes: {}",
+                    account.username,
+                    self.password_reset_ttl_secs / 60,
+                    token
+                ),
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Stores a reset token for the account registered with the email. Returns the account
+    /// and the token to send to it, or `None` if no account uses the email.
+    fn create_reset_token_blocking(
+        &self,
+        email: &str,
+    ) -> Result<Option<(Account, String)>, AuthError> {
+        let mut conn = self.db_pool.pool.get().map_err(DbError::from)?;
+        let Some(account) = accounts::table
+            .filter(accounts::email.eq(email))
+            .filter(accounts::deleted_at.is_null())
+            .first::<Account>(&mut conn)
+            .optional()
+            .map_err(DbError::from)?
+        else {
+            return Ok(None);
+        };
+
+        let (selector, verifier) = reset::generate_reset_token();
+        let verifier_hash = hash_password(&verifier)?;
+        let expires_at =
+            Utc::now().naive_utc() + chrono::Duration::seconds(self.password_reset_ttl_secs);
+        reset::create_reset_token(&mut conn, account.id, &selector, &verifier_hash, expires_at)
+            .map_err(DbError::from)?;
+
+        Ok(Some((account, format!("{}.{}", selector, verifier))))
+    }
+
+    /// Sets a new password using a token from `request_password_reset`. The token can only
+    /// be used once and all tokens of the account are revoked. Returns the account's id.
+    pub async fn reset_password(
+        self: &Arc<Self>,
+        token: &str,
+        new_password: &str,
+    ) -> Result<i32, AuthError> {
+        let (token, new_password) = (token.to_string(), new_password.to_string());
+        self.run_blocking(move |auth| auth.reset_password_blocking(&token, &new_password))
+            .await
+    }
+
+    fn reset_password_blocking(&self, token: &str, new_password: &str) -> Result<i32, AuthError> {
+        let (selector, verifier) =
+            reset::split_reset_token(token).ok_or(AuthError::InvalidResetToken)?;
+        let mut conn = self.db_pool.pool.get().map_err(DbError::from)?;
+
+        let reset_token = reset::find_reset_token(&mut conn, selector)
+            .map_err(DbError::from)?
+            .ok_or(AuthError::InvalidResetToken)?;
+        if !verify_password(verifier, &reset_token.verifier_hash)? {
+            return Err(AuthError::InvalidResetToken);
+        }
+        validate_password(new_password)?;
+
+        let password_hash = hash_password(new_password)?;
+        set_password(
+            &mut conn,
+            reset_token.account_id,
+            password_hash,
+            "password_reset",
+        )?;
+        Ok(reset_token.account_id)
+    }
+
+    /// Exchanges a valid refresh token for a new token pair. The refresh token is rotated,
+    /// so the one passed in cannot be used again.
+    pub async fn refresh(self: &Arc<Self>, refresh_token: &str) -> Result<TokenPair, AuthError> {
+        let refresh_token = refresh_token.to_string();
+        self.run_blocking(move |auth| auth.refresh_blocking(&refresh_token))
+            .await
+    }
+
+    fn refresh_blocking(&self, refresh_token: &str) -> Result<TokenPair, AuthError> {
+        let claims = self.validate_token_blocking(refresh_token, TokenKind::Refresh)?;
+        let account_id = claims.account_id()?;
+        let mut conn = self.db_pool.pool.get().map_err(DbError::from)?;
+
+        let account = accounts::table
+            .find(account_id)
+            .filter(accounts::deleted_at.is_null())
+            .first::<Account>(&mut conn)
+            .optional()
+            .map_err(DbError::from)?
+            .ok_or(AuthError::InvalidToken)?;
+
+        if account.is_active == Some(false) {
+            return Err(AuthError::AccountInactive);
+        }
+
+        revocation::revoke_token(&mut conn, account_id, &claims, "refreshed")
+            .map_err(DbError::from)?;
+
+        self.issue_tokens(&account)
+    }
+
+    /// Decodes a token, checks that it is of the expected kind and that it has not been
+    /// revoked, and returns its claims.
+    ///
+    /// This is the single entry point for token validation, used both during the WebSocket
+    /// handshake and by message handlers that require an authenticated session. The
+    /// revocation lookup runs on the blocking pool so that a slow database does not stall
+    /// the tables.
+    pub async fn validate_token(
+        self: &Arc<Self>,
+        token: &str,
+        kind: TokenKind,
+    ) -> Result<Claims, AuthError> {
+        let token = token.to_string();
+        self.run_blocking(move |auth| auth.validate_token_blocking(&token, kind))
+            .await
+    }
+
+    /// `validate_token` for callers that may block, i.e. that already run off the async
+    /// workers.
+    pub fn validate_token_blocking(
+        &self,
+        token: &str,
+        kind: TokenKind,
+    ) -> Result<Claims, AuthError> {
+        let claims = token::decode_token(token, &self.jwt_secret, &self.jwt_issuer, kind)?;
+
+        if claims.guest {
+            if !self.guests.contains(&claims.sub) {
+                return Err(AuthError::TokenRevoked);
+            }
+            return Ok(claims);
+        }
+
+        let account_id = claims.account_id()?;
+        let mut conn = self.db_pool.pool.get().map_err(DbError::from)?;
+        if revocation::is_revoked(&mut conn, account_id, &claims).map_err(DbError::from)? {
+            return Err(AuthError::TokenRevoked);
+        }
+
+        Ok(claims)
+    }
+
+    /// Runs work that blocks on the database or on argon2 on the blocking pool, so that it
+    /// does not hold up the async workers.
+    async fn run_blocking<T, F>(self: &Arc<Self>, work: F) -> Result<T, AuthError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&AuthService) -> Result<T, AuthError> + Send + 'static,
+    {
+        let auth = Arc::clone(self);
+        tokio::task::spawn_blocking(move || work(&auth)).await?
+    }
+
+    /// Revokes a single token, e.g. on logout. Revoking a guest's token ends the guest.
+    pub async fn revoke_token(
+        self: &Arc<Self>,
+        claims: &Claims,
+        reason: &str,
+    ) -> Result<(), AuthError> {
+        if claims.guest {
+            self.guests.remove(&claims.sub);
+            return Ok(());
+        }
+
+        let account_id = claims.account_id()?;
+        let (claims, reason) = (claims.clone(), reason.to_string());
+        self.run_blocking(move |auth| {
+            let mut conn = auth.db_pool.pool.get().map_err(DbError::from)?;
+            revocation::revoke_token(&mut conn, account_id, &claims, &reason)
+                .map_err(DbError::from)?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Revokes every token issued to an account so far, e.g. on logout from all devices.
+    pub async fn revoke_account_tokens(
+        self: &Arc<Self>,
+        account_id: i32,
+        reason: &str,
+    ) -> Result<(), AuthError> {
+        let reason = reason.to_string();
+        self.run_blocking(move |auth| {
+            let mut conn = auth.db_pool.pool.get().map_err(DbError::from)?;
+            revocation::revoke_all_tokens(&mut conn, account_id, &reason).map_err(DbError::from)?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Bans an account: deactivates it and revokes all of its tokens.
+    pub async fn ban_account(self: &Arc<Self>, account_id: i32) -> Result<(), AuthError> {
+        self.run_blocking(move |auth| {
+            let mut conn = auth.db_pool.pool.get().map_err(DbError::from)?;
+            conn.transaction(|conn| {
+                diesel::update(accounts::table.find(account_id))
+                    .set((
+                        accounts::is_active.eq(Some(false)),
+                        accounts::locked_until.eq(None::<NaiveDateTime>),
+                        accounts::updated_at.eq(Utc::now().naive_utc()),
+                    ))
+                    .execute(conn)?;
+                revocation::revoke_all_tokens(conn, account_id, "banned")
+            })
+            .map_err(DbError::from)?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn lockouts(self: &Arc<Self>) -> Result<Lockouts, AuthError> {
+        self.run_blocking(|auth| {
+            let mut conn = auth.db_pool.pool.get().map_err(DbError::from)?;
+            Ok(Lockouts {
+                accounts: lockout::locked_accounts(&mut conn).map_err(DbError::from)?,
+                throttled: auth.throttle.entries(),
+            })
+        })
+        .await
+    }
+
+    /// Lifts a lockout and the login backoff of the account. Returns `false` if the account
+    /// was not locked; banned accounts are not reactivated.
+    pub async fn unlock_account(self: &Arc<Self>, account_id: i32) -> Result<bool, AuthError> {
+        self.run_blocking(move |auth| {
+            let mut conn = auth.db_pool.pool.get().map_err(DbError::from)?;
+            let username = lockout::unlock_account(&mut conn, account_id).map_err(DbError::from)?;
+            if let Some(username) = &username {
+                auth.throttle.clear(username);
+            }
+            Ok(username.is_some())
+        })
+        .await
+    }
+
+    pub async fn account_roles(self: &Arc<Self>, account_id: i32) -> Result<Vec<Role>, AuthError> {
+        self.run_blocking(move |auth| auth.account_roles_blocking(account_id))
+            .await
+    }
+
+    fn account_roles_blocking(&self, account_id: i32) -> Result<Vec<Role>, AuthError> {
+        let mut conn = self.db_pool.pool.get().map_err(DbError::from)?;
+        Ok(roles::load_account_roles(&mut conn, account_id).map_err(DbError::from)?)
+    }
+
+    /// Role changes apply to new tokens, i.e. after the next login or refresh.
+    pub async fn grant_role(
+        self: &Arc<Self>,
+        account_id: i32,
+        role: Role,
+    ) -> Result<(), AuthError> {
+        self.run_blocking(move |auth| {
+            let mut conn = auth.db_pool.pool.get().map_err(DbError::from)?;
+            roles::grant_role(&mut conn, account_id, role).map_err(DbError::from)?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn revoke_role(
+        self: &Arc<Self>,
+        account_id: i32,
+        role: Role,
+    ) -> Result<(), AuthError> {
+        self.run_blocking(move |auth| {
+            let mut conn = auth.db_pool.pool.get().map_err(DbError::from)?;
+            roles::revoke_role(&mut conn, account_id, role).map_err(DbError::from)?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Issues an access and refresh token for the account, embedding its current roles.
+    /// Blocks on the database, so callers on the async workers go through `run_blocking`.
+    pub fn issue_tokens(&self, account: &Account) -> Result<TokenPair, AuthError> {
+        let roles = self.account_roles_blocking(account.id)?;
+        let access = self.build_claims(account, &roles, TokenKind::Access, self.access_ttl_secs);
+        let refresh = self.build_claims(account, &roles, TokenKind::Refresh, self.refresh_ttl_secs);
+
+        Ok(TokenPair {
+            access_token: token::encode_token(&access, &self.jwt_secret)?,
+            refresh_token: token::encode_token(&refresh, &self.jwt_secret)?,
+            claims: access,
+            refresh_claims: refresh,
+            expires_in: self.access_ttl_secs,
+        })
+    }
+
+    fn build_claims(
+        &self,
+        account: &Account,
+        roles: &[Role],
+        kind: TokenKind,
+        ttl_secs: i64,
+    ) -> Claims {
+        let now = Utc::now().timestamp();
+        Claims {
+            sub: account.id.to_string(),
+            username: account.username.clone(),
+            iss: self.jwt_issuer.clone(),
+            iat: now,
+            exp: now + ttl_secs,
+            jti: Uuid::new_v4().to_string(),
+            typ: kind,
+            roles: roles.to_vec(),
+            guest: false,
+        }
+    }
+}
+
+/// Stores a new password hash, consumes pending reset tokens and revokes every token of the
+/// account in a single transaction.
+fn set_password(
+    conn: &mut PgConnection,
+    account_id: i32,
+    password_hash: String,
+    reason: &str,
+) -> Result<(), AuthError> {
+    conn.transaction(|conn| {
+        diesel::update(accounts::table.find(account_id))
+            .set(&UpdateAccount {
+                password: Some(password_hash),
+                updated_at: Some(Utc::now().naive_utc()),
+                ..Default::default()
+            })
+            .execute(conn)?;
+        reset::consume_reset_tokens(conn, account_id)?;
+        revocation::revoke_all_tokens(conn, account_id, reason)
+    })
+    .map_err(DbError::from)?;
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use argon2::password_hash::{
+    rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+};
+use argon2::Argon2;
+use std::sync::LazyLock;
+
+use crate::auth::AuthError;
+
+/// Hash checked against when there is no account to check the password of, so that unknown
+/// usernames take as long to reject as wrong passwords.
+static DUMMY_HASH: LazyLock<Option<String>> =
+    LazyLock::new(|| hash_password("not the password of any account").ok());
+
+/// Hashes a plain text password with argon2 using a freshly generated salt.
+///
+/// The returned string is in PHC format and is what gets stored in `accounts.password`.
+pub fn hash_password(password: &str) -> Result<String, AuthError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| AuthError::PasswordHash(e.to_string()))
+}
+
+/// Checks a plain text password against a PHC formatted argon2 hash.
+///
+/// Returns `Ok(false)` when the password does not match and an error only when the
+/// stored hash itself cannot be parsed.
+pub fn verify_password(password: &str, password_hash: &str) -> Result<bool, AuthError> {
+    let parsed_hash =
+        PasswordHash::new(password_hash).map_err(|e| AuthError::PasswordHash(e.to_string()))?;
+
+    Ok(Argon2::default()
+        .verify_password(password.as_bytes(), &parsed_hash)
+        .is_ok())
+}
+
+/// Spends the time of a `verify_password` without a hash to verify against.
+pub fn verify_dummy_password(password: &str) {
+    if let Some(hash) = DUMMY_HASH.as_deref() {
+        let _ = verify_password(password, hash);
+    }
+}
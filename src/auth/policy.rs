@@ -0,0 +1,149 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use ts_rs::TS;
+
+use crate::auth::{Claims, Role};
+use crate::message::{AdminCommand, ClientMessage};
+
+/// Actions that are restricted to some roles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, TS)]
+pub enum Permission {
+    JoinHighLimitTable,
+    CreatePrivateRoom,
+    ViewAccounts,
+    ManageAccounts,
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum PermissionError {
+    #[error("Not authenticated")]
+    Unauthenticated,
+    #[error("Missing permission {0:?}")]
+    Forbidden(Permission),
+}
+
+impl Role {
+    pub fn permissions(&self) -> &'static [Permission] {
+        match self {
+            Role::Player => &[],
+            Role::Vip => &[
+                Permission::JoinHighLimitTable,
+                Permission::CreatePrivateRoom,
+            ],
+            Role::Support => &[Permission::ViewAccounts],
+            Role::Admin => &[
+                Permission::JoinHighLimitTable,
+                Permission::CreatePrivateRoom,
+                Permission::ViewAccounts,
+                Permission::ManageAccounts,
+            ],
+        }
+    }
+}
+
+impl AdminCommand {
+    pub fn required_permission(&self) -> Permission {
+        match self {
+            AdminCommand::ListRoles { .. }
+            | AdminCommand::ListLockouts
+            | AdminCommand::ListSessions { .. }
+            | AdminCommand::ConnectionStats => Permission::ViewAccounts,
+            AdminCommand::GrantRole { .. }
+            | AdminCommand::RevokeRole { .. }
+            | AdminCommand::BanAccount { .. }
+            | AdminCommand::UnlockAccount { .. }
+            | AdminCommand::EndSession { .. } => Permission::ManageAccounts,
+        }
+    }
+}
+
+/// Returns the permission a client message needs, or `None` if any client may send it.
+pub fn required_permission(message: &ClientMessage) -> Option<Permission> {
+    match message {
+        ClientMessage::SelectGame {
+            high_limit: true, ..
+        } => Some(Permission::JoinHighLimitTable),
+        ClientMessage::CreatePrivateRoom { .. } => Some(Permission::CreatePrivateRoom),
+        ClientMessage::Admin { command } => Some(command.required_permission()),
+        _ => None,
+    }
+}
+
+/// Checks that the session behind `claims` holds a role granting `permission`.
+pub fn authorize(claims: Option<&Claims>, permission: Permission) -> Result<(), PermissionError> {
+    let claims = claims.ok_or(PermissionError::Unauthenticated)?;
+
+    if claims
+        .roles
+        .iter()
+        .any(|role| role.permissions().contains(&permission))
+    {
+        Ok(())
+    } else {
+        Err(PermissionError::Forbidden(permission))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::TokenKind;
+
+    fn claims(roles: Vec<Role>) -> Claims {
+        Claims {
+            sub: "1".to_string(),
+            username: "alice".to_string(),
+            iss: "test".to_string(),
+            iat: 0,
+            exp: 0,
+            jti: "jti".to_string(),
+            typ: TokenKind::Access,
+            roles,
+            guest: false,
+        }
+    }
+
+    #[test]
+    fn requires_a_session() {
+        assert_eq!(
+            authorize(None, Permission::CreatePrivateRoom),
+            Err(PermissionError::Unauthenticated)
+        );
+    }
+
+    #[test]
+    fn grants_permissions_of_any_role() {
+        let vip = claims(vec![Role::Player, Role::Vip]);
+        assert_eq!(authorize(Some(&vip), Permission::CreatePrivateRoom), Ok(()));
+        assert_eq!(
+            authorize(Some(&vip), Permission::ViewAccounts),
+            Err(PermissionError::Forbidden(Permission::ViewAccounts))
+        );
+
+        let admin = claims(vec![Role::Admin]);
+        assert_eq!(authorize(Some(&admin), Permission::ManageAccounts), Ok(()));
+    }
+
+    #[test]
+    fn players_hold_no_permissions() {
+        let player = claims(vec![Role::Player]);
+        assert_eq!(
+            authorize(Some(&player), Permission::JoinHighLimitTable),
+            Err(PermissionError::Forbidden(Permission::JoinHighLimitTable))
+        );
+    }
+
+    #[test]
+    fn support_may_view_but_not_manage_accounts() {
+        let support = claims(vec![Role::Support]);
+        let view = AdminCommand::ListLockouts.required_permission();
+        let manage = AdminCommand::BanAccount { account_id: 1 }.required_permission();
+
+        assert_eq!(authorize(Some(&support), view), Ok(()));
+        assert_eq!(
+            authorize(Some(&support), manage),
+            Err(PermissionError::Forbidden(Permission::ManageAccounts))
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+
+use crate::auth::AuthError;
+use crate::db::DbError;
+
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// The details a new player supplies when creating an account.
+pub struct Registration {
+    pub username: String,
+    pub email: String,
+    pub password: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub citizen_id: String,
+}
+
+impl Registration {
+    /// Checks the registration details against the column limits of the `users` and
+    /// `accounts` tables before anything is written.
+    pub fn validate(&self) -> Result<(), AuthError> {
+        let username_len = self.username.chars().count();
+        if !(3..=50).contains(&username_len)
+            || !self
+                .username
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(AuthError::InvalidRegistration {
+                field: "username",
+                reason: "must be 3 to 50 letters, digits, '_' or '-'",
+            });
+        }
+
+        let email_valid = self.email.len() <= 255
+            && self
+                .email
+                .split_once('@')
+                .is_some_and(|(local, domain)| !local.is_empty() && domain.contains('.'));
+        if !email_valid {
+            return Err(AuthError::InvalidRegistration {
+                field: "email",
+                reason: "must be a valid email address",
+            });
+        }
+
+        validate_password(&self.password)?;
+
+        for (field, value, max_len) in [
+            ("first_name", &self.first_name, 100),
+            ("last_name", &self.last_name, 100),
+            ("citizen_id", &self.citizen_id, 50),
+        ] {
+            let len = value.trim().chars().count();
+            if len == 0 || len > max_len {
+                return Err(AuthError::InvalidRegistration {
+                    field,
+                    reason: "must not be empty or longer than the allowed length",
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks a new password against the password policy, on registration and when a password
+/// is changed or reset.
+pub fn validate_password(password: &str) -> Result<(), AuthError> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(AuthError::InvalidRegistration {
+            field: "password",
+            reason: "must be at least 8 characters long",
+        });
+    }
+    Ok(())
+}
+
+/// Maps unique constraint violations raised while inserting a `users` or `accounts` row
+/// to the matching `AuthError`, so clients are told which field is already in use.
+pub fn map_registration_error(error: DieselError) -> AuthError {
+    if let DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, ref info) = error {
+        match info.constraint_name() {
+            Some("accounts_username_key") => return AuthError::UsernameTaken,
+            Some("accounts_email_key") => return AuthError::EmailTaken,
+            Some("users_citizen_id_key") => return AuthError::CitizenIdTaken,
+            _ => {}
+        }
+    }
+    AuthError::Db(DbError::from(error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registration() -> Registration {
+        Registration {
+            username: "alice_01".to_string(),
+            email: "alice@example.com".to_string(),
+            password: "correct horse".to_string(),
+            first_name: "Alice".to_string(),
+            last_name: "Liddell".to_string(),
+            citizen_id: "8001015009087".to_string(),
+        }
+    }
+
+    fn rejected_field(registration: Registration) -> Option<&'static str> {
+        match registration.validate() {
+            Err(AuthError::InvalidRegistration { field, .. }) => Some(field),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn accepts_valid_details() {
+        assert!(registration().validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_usernames() {
+        for username in ["al", "alice smith", "alice!", &"a".repeat(51)] {
+            let registration = Registration {
+                username: username.to_string(),
+                ..registration()
+            };
+            assert_eq!(
+                rejected_field(registration),
+                Some("username"),
+                "{}",
+                username
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_emails() {
+        for email in ["alice", "@example.com", "alice@localhost"] {
+            let registration = Registration {
+                email: email.to_string(),
+                ..registration()
+            };
+            assert_eq!(rejected_field(registration), Some("email"), "{}", email);
+        }
+    }
+
+    #[test]
+    fn rejects_short_passwords() {
+        let registration = Registration {
+            password: "short".to_string(),
+            ..registration()
+        };
+        assert_eq!(rejected_field(registration), Some("password"));
+    }
+
+    #[test]
+    fn rejects_blank_or_long_names() {
+        let blank = Registration {
+            first_name: "  ".to_string(),
+            ..registration()
+        };
+        assert_eq!(rejected_field(blank), Some("first_name"));
+
+        let long = Registration {
+            citizen_id: "1".repeat(51),
+            ..registration()
+        };
+        assert_eq!(rejected_field(long), Some("citizen_id"));
+    }
+}
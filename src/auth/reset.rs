@@ -0,0 +1,77 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel::PgConnection;
+
+use crate::models::{NewPasswordResetToken, PasswordResetToken};
+use crate::schema::password_reset_tokens;
+
+const SELECTOR_BYTES: usize = 12;
+const VERIFIER_BYTES: usize = 32;
+
+fn random_hex(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Generates the selector and verifier of a new reset token. The token handed to the player
+/// is `<selector>.<verifier>`.
+pub fn generate_reset_token() -> (String, String) {
+    (random_hex(SELECTOR_BYTES), random_hex(VERIFIER_BYTES))
+}
+
+/// Splits a reset token into its selector and verifier.
+pub fn split_reset_token(token: &str) -> Option<(&str, &str)> {
+    token
+        .split_once('.')
+        .filter(|(selector, verifier)| !selector.is_empty() && !verifier.is_empty())
+}
+
+/// Stores a new reset token and invalidates any earlier one of the account, so only the
+/// most recently sent link works.
+pub fn create_reset_token(
+    conn: &mut PgConnection,
+    account_id: i32,
+    selector: &str,
+    verifier_hash: &str,
+    expires_at: NaiveDateTime,
+) -> QueryResult<()> {
+    conn.transaction(|conn| {
+        consume_reset_tokens(conn, account_id)?;
+        diesel::insert_into(password_reset_tokens::table)
+            .values(&NewPasswordResetToken {
+                account_id,
+                selector: selector.to_string(),
+                verifier_hash: verifier_hash.to_string(),
+                expires_at,
+            })
+            .execute(conn)?;
+        Ok(())
+    })
+}
+
+/// Looks up an unused and unexpired reset token by its selector.
+pub fn find_reset_token(
+    conn: &mut PgConnection,
+    selector: &str,
+) -> QueryResult<Option<PasswordResetToken>> {
+    password_reset_tokens::table
+        .filter(password_reset_tokens::selector.eq(selector))
+        .filter(password_reset_tokens::used_at.is_null())
+        .filter(password_reset_tokens::expires_at.gt(Utc::now().naive_utc()))
+        .first(conn)
+        .optional()
+}
+
+/// Marks every pending reset token of the account as used.
+pub fn consume_reset_tokens(conn: &mut PgConnection, account_id: i32) -> QueryResult<()> {
+    diesel::update(
+        password_reset_tokens::table
+            .filter(password_reset_tokens::account_id.eq(account_id))
+            .filter(password_reset_tokens::used_at.is_null()),
+    )
+    .set(password_reset_tokens::used_at.eq(Utc::now().naive_utc()))
+    .execute(conn)?;
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use chrono::{DateTime, Duration, NaiveDateTime};
+use diesel::prelude::*;
+use diesel::PgConnection;
+
+use crate::auth::Claims;
+use crate::models::NewRevokedToken;
+use crate::schema::revoked_tokens;
+
+fn timestamp_to_naive(timestamp: i64) -> NaiveDateTime {
+    DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .naive_utc()
+}
+
+/// Revokes a single token by its `jti`. Revoking the same token twice is a no-op.
+pub fn revoke_token(
+    conn: &mut PgConnection,
+    account_id: i32,
+    claims: &Claims,
+    reason: &str,
+) -> QueryResult<()> {
+    diesel::insert_into(revoked_tokens::table)
+        .values(&NewRevokedToken {
+            account_id,
+            jti: Some(claims.jti.clone()),
+            reason: Some(reason.to_string()),
+            expires_at: Some(timestamp_to_naive(claims.exp)),
+        })
+        .on_conflict(revoked_tokens::jti)
+        .do_nothing()
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Revokes every token issued to the account up to now.
+pub fn revoke_all_tokens(
+    conn: &mut PgConnection,
+    account_id: i32,
+    reason: &str,
+) -> QueryResult<()> {
+    diesel::insert_into(revoked_tokens::table)
+        .values(&NewRevokedToken {
+            account_id,
+            jti: None,
+            reason: Some(reason.to_string()),
+            expires_at: None,
+        })
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Checks whether the token itself, or every token of its account issued before a
+/// given point in time, has been revoked.
+///
+/// `iat` only has whole seconds, so revocations are compared at that granularity too: a token
+/// issued within the second of a revocation, e.g. by logging back in right after a password
+/// change, stays valid.
+pub fn is_revoked(conn: &mut PgConnection, account_id: i32, claims: &Claims) -> QueryResult<bool> {
+    let next_second = timestamp_to_naive(claims.iat) + Duration::seconds(1);
+
+    diesel::select(diesel::dsl::exists(
+        revoked_tokens::table.filter(
+            revoked_tokens::jti
+                .eq(&claims.jti)
+                .or(revoked_tokens::account_id
+                    .eq(account_id)
+                    .and(revoked_tokens::jti.is_null())
+                    .and(revoked_tokens::revoked_at.ge(next_second))),
+        ),
+    ))
+    .get_result(conn)
+}
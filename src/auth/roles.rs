@@ -0,0 +1,100 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel::PgConnection;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::models::NewAccountRole;
+use crate::schema::{account_roles, roles};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Player,
+    Vip,
+    Support,
+    Admin,
+}
+
+impl Role {
+    /// The `roles.code` value this role is stored under.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Role::Player => "player",
+            Role::Vip => "vip",
+            Role::Support => "support",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "player" => Some(Role::Player),
+            "vip" => Some(Role::Vip),
+            "support" => Some(Role::Support),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+/// Loads the roles currently assigned to an account. Unknown role codes are ignored.
+pub fn load_account_roles(conn: &mut PgConnection, account_id: i32) -> QueryResult<Vec<Role>> {
+    let codes = account_roles::table
+        .inner_join(roles::table)
+        .filter(account_roles::account_id.eq(account_id))
+        .filter(account_roles::deleted_at.is_null())
+        .filter(roles::deleted_at.is_null())
+        .select(roles::code)
+        .load::<Option<String>>(conn)?;
+
+    Ok(codes
+        .iter()
+        .flatten()
+        .filter_map(|code| Role::from_code(code))
+        .collect())
+}
+
+/// Assigns a role to an account, restoring a previously removed assignment if there is one.
+pub fn grant_role(conn: &mut PgConnection, account_id: i32, role: Role) -> QueryResult<()> {
+    let role_id = roles::table
+        .filter(roles::code.eq(role.code()))
+        .select(roles::id)
+        .first::<i32>(conn)?;
+
+    diesel::insert_into(account_roles::table)
+        .values(&NewAccountRole {
+            account_id: Some(account_id),
+            role_id: Some(role_id),
+        })
+        .on_conflict((account_roles::account_id, account_roles::role_id))
+        .do_update()
+        .set((
+            account_roles::deleted_at.eq(None::<NaiveDateTime>),
+            account_roles::updated_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Soft deletes a role assignment.
+pub fn revoke_role(conn: &mut PgConnection, account_id: i32, role: Role) -> QueryResult<()> {
+    let role_id = roles::table
+        .filter(roles::code.eq(role.code()))
+        .select(roles::id)
+        .first::<i32>(conn)?;
+
+    let now = Utc::now().naive_utc();
+    diesel::update(
+        account_roles::table
+            .filter(account_roles::account_id.eq(account_id))
+            .filter(account_roles::role_id.eq(role_id)),
+    )
+    .set((
+        account_roles::deleted_at.eq(now),
+        account_roles::updated_at.eq(now),
+    ))
+    .execute(conn)?;
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long a throttle entry is remembered after its backoff has passed.
+const FORGET_AFTER: Duration = Duration::from_secs(60 * 60);
+
+struct FailureRecord {
+    failures: u32,
+    blocked_until: Instant,
+}
+
+/// A username or address that currently has to wait before trying to log in again.
+#[derive(Serialize)]
+pub struct ThrottleEntry {
+    pub key: String,
+    pub failures: u32,
+    pub retry_after_secs: u64,
+}
+
+/// In-memory throttle of failed logins, keyed both by username and by client address.
+///
+/// Every failure doubles the time the key has to wait before the next attempt, starting at
+/// `base_delay` and capped at `max_delay`. A successful login clears the username's record.
+pub struct LoginThrottle {
+    records: Mutex<HashMap<String, FailureRecord>>,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+fn username_key(username: &str) -> String {
+    format!("user:{}", username.to_lowercase())
+}
+
+fn ip_key(ip: IpAddr) -> String {
+    format!("ip:{}", ip)
+}
+
+impl LoginThrottle {
+    pub fn new(base_delay: Duration, max_delay: Duration) -> Self {
+        LoginThrottle {
+            records: Mutex::new(HashMap::new()),
+            base_delay,
+            max_delay,
+        }
+    }
+
+    /// Returns how long the caller has to wait if either the username or the address is
+    /// still backing off.
+    pub fn check(&self, username: &str, ip: IpAddr) -> Option<Duration> {
+        let records = self.records.lock().unwrap();
+        let now = Instant::now();
+        [username_key(username), ip_key(ip)]
+            .iter()
+            .filter_map(|key| records.get(key))
+            .filter_map(|record| record.blocked_until.checked_duration_since(now))
+            .max()
+    }
+
+    pub fn record_failure(&self, username: &str, ip: IpAddr) {
+        let mut records = self.records.lock().unwrap();
+        let now = Instant::now();
+        records.retain(|_, record| record.blocked_until + FORGET_AFTER > now);
+
+        for key in [username_key(username), ip_key(ip)] {
+            let record = records.entry(key).or_insert(FailureRecord {
+                failures: 0,
+                blocked_until: now,
+            });
+            record.failures = record.failures.saturating_add(1);
+            let delay = self
+                .base_delay
+                .saturating_mul(2u32.saturating_pow(record.failures - 1))
+                .min(self.max_delay);
+            record.blocked_until = now + delay;
+        }
+    }
+
+    /// Forgets the failures of a username, e.g. after a successful login or an admin unlock.
+    /// The address keeps its record so that one valid account cannot be used to reset it.
+    pub fn clear(&self, username: &str) {
+        self.records.lock().unwrap().remove(&username_key(username));
+    }
+
+    pub fn entries(&self) -> Vec<ThrottleEntry> {
+        let records = self.records.lock().unwrap();
+        let now = Instant::now();
+        records
+            .iter()
+            .filter_map(|(key, record)| {
+                let remaining = record.blocked_until.checked_duration_since(now)?;
+                Some(ThrottleEntry {
+                    key: key.clone(),
+                    failures: record.failures,
+                    retry_after_secs: remaining.as_secs_f64().ceil() as u64,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: Duration = Duration::from_secs(10);
+    const MAX: Duration = Duration::from_secs(30);
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([192, 0, 2, last])
+    }
+
+    #[test]
+    fn lets_unknown_keys_through() {
+        let throttle = LoginThrottle::new(BASE, MAX);
+        assert_eq!(throttle.check("alice", ip(1)), None);
+    }
+
+    #[test]
+    fn doubles_the_delay_up_to_the_cap() {
+        let throttle = LoginThrottle::new(BASE, MAX);
+
+        throttle.record_failure("alice", ip(1));
+        let wait = throttle.check("alice", ip(2)).unwrap();
+        assert!(wait > BASE - Duration::from_secs(1) && wait <= BASE);
+
+        throttle.record_failure("alice", ip(1));
+        let wait = throttle.check("alice", ip(2)).unwrap();
+        assert!(wait > 2 * BASE - Duration::from_secs(1) && wait <= 2 * BASE);
+
+        throttle.record_failure("alice", ip(1));
+        throttle.record_failure("alice", ip(1));
+        assert!(throttle.check("alice", ip(2)).unwrap() <= MAX);
+    }
+
+    #[test]
+    fn throttles_by_username_and_by_address() {
+        let throttle = LoginThrottle::new(BASE, MAX);
+        throttle.record_failure("alice", ip(1));
+
+        assert!(throttle.check("ALICE", ip(2)).is_some());
+        assert!(throttle.check("bob", ip(1)).is_some());
+        assert_eq!(throttle.check("bob", ip(2)), None);
+    }
+
+    #[test]
+    fn clearing_a_username_keeps_the_address_throttled() {
+        let throttle = LoginThrottle::new(BASE, MAX);
+        throttle.record_failure("alice", ip(1));
+        throttle.clear("alice");
+
+        assert_eq!(throttle.check("alice", ip(2)), None);
+        assert!(throttle.check("alice", ip(1)).is_some());
+
+        let mut keys: Vec<String> = throttle.entries().into_iter().map(|e| e.key).collect();
+        keys.sort();
+        assert_eq!(keys, vec!["ip:192.0.2.1".to_string()]);
+    }
+}
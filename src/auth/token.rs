@@ -0,0 +1,134 @@
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::{AuthError, Role};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenKind {
+    Access,
+    Refresh,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    /// Account id the token was issued for, or the player id of a guest.
+    pub sub: String,
+    pub username: String,
+    pub iss: String,
+    pub iat: i64,
+    pub exp: i64,
+    /// Unique token id, used to revoke a single token.
+    pub jti: String,
+    pub typ: TokenKind,
+    #[serde(default)]
+    pub roles: Vec<Role>,
+    /// Guests have no account and may only join free-play rooms.
+    #[serde(default)]
+    pub guest: bool,
+}
+
+impl Claims {
+    pub fn account_id(&self) -> Result<i32, AuthError> {
+        self.sub.parse().map_err(|_| AuthError::InvalidToken)
+    }
+}
+
+pub fn encode_token(claims: &Claims, secret: &str) -> Result<String, AuthError> {
+    Ok(encode(
+        &Header::default(),
+        claims,
+        &EncodingKey::from_secret(secret.as_bytes()),
+    )?)
+}
+
+/// Verifies the signature, issuer, expiry and kind of a token and returns its claims.
+///
+/// This does not consult the revocation store, see `AuthService::validate_token`.
+pub fn decode_token(
+    token: &str,
+    secret: &str,
+    issuer: &str,
+    kind: TokenKind,
+) -> Result<Claims, AuthError> {
+    let mut validation = Validation::default();
+    validation.set_issuer(&[issuer]);
+
+    let data = decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(secret.as_bytes()),
+        &validation,
+    )?;
+    if data.claims.typ != kind {
+        return Err(AuthError::InvalidToken);
+    }
+    Ok(data.claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use jsonwebtoken::errors::ErrorKind;
+
+    const SECRET: &str = "secret";
+    const ISSUER: &str = "ro-rust";
+
+    fn claims(kind: TokenKind, expires_in_secs: i64) -> Claims {
+        let now = Utc::now().timestamp();
+        Claims {
+            sub: "42".to_string(),
+            username: "alice".to_string(),
+            iss: ISSUER.to_string(),
+            iat: now,
+            exp: now + expires_in_secs,
+            jti: "jti".to_string(),
+            typ: kind,
+            roles: vec![Role::Player],
+            guest: false,
+        }
+    }
+
+    fn decode(claims: &Claims, kind: TokenKind) -> Result<Claims, AuthError> {
+        let token = encode_token(claims, SECRET).unwrap();
+        decode_token(&token, SECRET, ISSUER, kind)
+    }
+
+    #[test]
+    fn decodes_tokens_of_the_expected_kind() {
+        let access = decode(&claims(TokenKind::Access, 900), TokenKind::Access).unwrap();
+        assert_eq!(access.account_id().unwrap(), 42);
+        assert_eq!(access.roles, vec![Role::Player]);
+
+        let refresh = decode(&claims(TokenKind::Refresh, 900), TokenKind::Refresh).unwrap();
+        assert_eq!(refresh.typ, TokenKind::Refresh);
+    }
+
+    #[test]
+    fn rejects_a_refresh_token_used_as_access_token_and_back() {
+        assert!(matches!(
+            decode(&claims(TokenKind::Refresh, 900), TokenKind::Access),
+            Err(AuthError::InvalidToken)
+        ));
+        assert!(matches!(
+            decode(&claims(TokenKind::Access, 900), TokenKind::Refresh),
+            Err(AuthError::InvalidToken)
+        ));
+    }
+
+    #[test]
+    fn rejects_expired_tokens() {
+        // Past the 60s leeway jsonwebtoken allows for clock skew.
+        match decode(&claims(TokenKind::Refresh, -120), TokenKind::Refresh) {
+            Err(AuthError::Token(e)) => assert_eq!(e.kind(), &ErrorKind::ExpiredSignature),
+            other => panic!("expected an expired token, got {:?}", other.map(|c| c.exp)),
+        }
+    }
+
+    #[test]
+    fn rejects_other_issuers_and_secrets() {
+        let token = encode_token(&claims(TokenKind::Access, 900), SECRET).unwrap();
+        assert!(decode_token(&token, "other", ISSUER, TokenKind::Access).is_err());
+        assert!(decode_token(&token, SECRET, "other", TokenKind::Access).is_err());
+    }
+}
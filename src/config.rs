@@ -1,14 +1,48 @@
 use anyhow::Context;
 use configparser::ini::Ini;
+use rust_decimal::Decimal;
 use serde::Deserialize;
+use std::collections::HashMap;
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
     pub server_address: String,
     pub server_port: u16,
     pub server_password: Option<String>,
+    pub resume_grace_secs: u64,
+    pub shutdown_timeout_secs: u64,
+    pub ping_interval_secs: u64,
+    pub idle_timeout_secs: u64,
+    pub tls_cert_path: Option<String>,
+    pub tls_key_path: Option<String>,
+    pub tls_reload_interval_secs: u64,
+    pub http_port: Option<u16>,
+    pub max_connections: usize,
+    pub max_connections_per_ip: usize,
+    pub max_message_bytes: usize,
+    pub messages_per_sec: f64,
+    pub message_burst: u32,
+    pub max_rate_violations: u32,
+    pub rate_violation_window_secs: u64,
+    /// `<rate>/<burst>` limits of single message types, keyed by snake_case type name.
+    pub message_type_limits: HashMap<String, String>,
+    pub compression_enabled: bool,
+    pub compression_threshold_bytes: usize,
     pub jwt_secret: Option<String>,
     pub jwt_issuer: Option<String>,
+    pub jwt_access_ttl_secs: i64,
+    pub jwt_refresh_ttl_secs: i64,
+    pub max_failed_logins: i32,
+    pub lockout_secs: i64,
+    pub login_backoff_base_ms: u64,
+    pub login_backoff_max_secs: u64,
+    pub password_reset_ttl_secs: i64,
+    pub notifier_sink: String,
+    pub notifier_path: Option<String>,
+    pub max_sessions_per_account: usize,
+    pub session_limit_policy: String,
+    pub guest_ttl_secs: i64,
+    pub guest_starting_balance: Decimal,
 }
 
 #[allow(dead_code)]
@@ -17,7 +51,7 @@ impl Config {
         let mut config = Ini::new();
         config.load(file_path).expect("Failed to load config file");
 
-        Ok(Config {
+        let config = Config {
             server_address: config
                 .get("server", "address")
                 .context("Missing server address")?,
@@ -26,9 +60,164 @@ impl Config {
                 .context("Missing server port")?
                 .parse()?,
             server_password: config.get("server", "password").or(None),
+            resume_grace_secs: config
+                .get("server", "resume_grace_secs")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(60),
+            shutdown_timeout_secs: config
+                .get("server", "shutdown_timeout_secs")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(30),
+            ping_interval_secs: config
+                .get("server", "ping_interval_secs")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(30),
+            idle_timeout_secs: config
+                .get("server", "idle_timeout_secs")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(90),
+            tls_cert_path: config.get("tls", "cert_path").or(None),
+            tls_key_path: config.get("tls", "key_path").or(None),
+            tls_reload_interval_secs: config
+                .get("tls", "reload_interval_secs")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(300),
+            http_port: config.get("http", "port").map(|v| v.parse()).transpose()?,
+            max_connections: config
+                .get("limits", "max_connections")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(10_000),
+            max_connections_per_ip: config
+                .get("limits", "max_connections_per_ip")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(20),
+            max_message_bytes: config
+                .get("limits", "max_message_bytes")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(64 * 1024),
+            messages_per_sec: config
+                .get("limits", "messages_per_sec")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(20.0),
+            message_burst: config
+                .get("limits", "burst")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(40),
+            max_rate_violations: config
+                .get("limits", "max_violations")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(10),
+            rate_violation_window_secs: config
+                .get("limits", "violation_window_secs")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(60),
+            message_type_limits: config
+                .get_map_ref()
+                .get("message_limits")
+                .map(|section| {
+                    section
+                        .iter()
+                        .filter_map(|(k, v)| Some((k.clone(), v.clone()?)))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            compression_enabled: config
+                .get("compression", "enabled")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(true),
+            compression_threshold_bytes: config
+                .get("compression", "threshold_bytes")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(1024),
             jwt_secret: config.get("jwt", "secret").or(None),
             jwt_issuer: config.get("jwt", "issuer").or(None),
-        })
+            jwt_access_ttl_secs: config
+                .get("jwt", "access_ttl_secs")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(15 * 60),
+            jwt_refresh_ttl_secs: config
+                .get("jwt", "refresh_ttl_secs")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(30 * 24 * 60 * 60),
+            max_failed_logins: config
+                .get("login", "max_failed_attempts")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(5),
+            lockout_secs: config
+                .get("login", "lockout_secs")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(15 * 60),
+            login_backoff_base_ms: config
+                .get("login", "backoff_base_ms")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(500),
+            login_backoff_max_secs: config
+                .get("login", "backoff_max_secs")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(5 * 60),
+            password_reset_ttl_secs: config
+                .get("login", "password_reset_ttl_secs")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(60 * 60),
+            notifier_sink: config
+                .get("notifier", "sink")
+                .unwrap_or_else(|| "log".to_string()),
+            notifier_path: config.get("notifier", "path").or(None),
+            max_sessions_per_account: config
+                .get("sessions", "max_per_account")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(5),
+            session_limit_policy: config
+                .get("sessions", "on_limit")
+                .unwrap_or_else(|| "end_oldest".to_string()),
+            guest_ttl_secs: config
+                .get("guest", "token_ttl_secs")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(24 * 60 * 60),
+            guest_starting_balance: config
+                .get("guest", "starting_balance")
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(Decimal::from(10_000)),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Rejects values that parse but that the server cannot run with.
+    fn validate(&self) -> Result<(), anyhow::Error> {
+        anyhow::ensure!(
+            self.ping_interval_secs > 0,
+            "server.ping_interval_secs must be greater than 0"
+        );
+        anyhow::ensure!(
+            self.idle_timeout_secs > 0,
+            "server.idle_timeout_secs must be greater than 0"
+        );
+        Ok(())
     }
 
     pub fn server_port_mut(&mut self) -> &mut u16 {
@@ -39,6 +228,78 @@ impl Config {
         &mut self.server_password
     }
 
+    pub fn resume_grace_secs_mut(&mut self) -> &mut u64 {
+        &mut self.resume_grace_secs
+    }
+
+    pub fn shutdown_timeout_secs_mut(&mut self) -> &mut u64 {
+        &mut self.shutdown_timeout_secs
+    }
+
+    pub fn ping_interval_secs_mut(&mut self) -> &mut u64 {
+        &mut self.ping_interval_secs
+    }
+
+    pub fn idle_timeout_secs_mut(&mut self) -> &mut u64 {
+        &mut self.idle_timeout_secs
+    }
+
+    pub fn tls_cert_path_mut(&mut self) -> &mut Option<String> {
+        &mut self.tls_cert_path
+    }
+
+    pub fn tls_key_path_mut(&mut self) -> &mut Option<String> {
+        &mut self.tls_key_path
+    }
+
+    pub fn tls_reload_interval_secs_mut(&mut self) -> &mut u64 {
+        &mut self.tls_reload_interval_secs
+    }
+
+    pub fn http_port_mut(&mut self) -> &mut Option<u16> {
+        &mut self.http_port
+    }
+
+    pub fn max_connections_mut(&mut self) -> &mut usize {
+        &mut self.max_connections
+    }
+
+    pub fn max_connections_per_ip_mut(&mut self) -> &mut usize {
+        &mut self.max_connections_per_ip
+    }
+
+    pub fn max_message_bytes_mut(&mut self) -> &mut usize {
+        &mut self.max_message_bytes
+    }
+
+    pub fn messages_per_sec_mut(&mut self) -> &mut f64 {
+        &mut self.messages_per_sec
+    }
+
+    pub fn message_burst_mut(&mut self) -> &mut u32 {
+        &mut self.message_burst
+    }
+
+    pub fn max_rate_violations_mut(&mut self) -> &mut u32 {
+        &mut self.max_rate_violations
+    }
+
+    pub fn rate_violation_window_secs_mut(&mut self) -> &mut u64 {
+        &mut self.rate_violation_window_secs
+    }
+
+    pub fn message_type_limits_mut(&mut self) -> &mut HashMap<String, String> {
+        &mut self.message_type_limits
+    }
+
+    pub fn compression_enabled_mut(&mut self) -> &mut bool {
+        &mut self.compression_enabled
+    }
+
+    pub fn compression_threshold_bytes_mut(&mut self) -> &mut usize {
+        &mut self.compression_threshold_bytes
+    }
+
     pub fn jwt_secret_mut(&mut self) -> &mut Option<String> {
         &mut self.jwt_secret
     }
@@ -46,4 +307,91 @@ impl Config {
     pub fn jwt_issuer_mut(&mut self) -> &mut Option<String> {
         &mut self.jwt_issuer
     }
+
+    pub fn jwt_access_ttl_secs_mut(&mut self) -> &mut i64 {
+        &mut self.jwt_access_ttl_secs
+    }
+
+    pub fn jwt_refresh_ttl_secs_mut(&mut self) -> &mut i64 {
+        &mut self.jwt_refresh_ttl_secs
+    }
+
+    pub fn max_failed_logins_mut(&mut self) -> &mut i32 {
+        &mut self.max_failed_logins
+    }
+
+    pub fn lockout_secs_mut(&mut self) -> &mut i64 {
+        &mut self.lockout_secs
+    }
+
+    pub fn login_backoff_base_ms_mut(&mut self) -> &mut u64 {
+        &mut self.login_backoff_base_ms
+    }
+
+    pub fn login_backoff_max_secs_mut(&mut self) -> &mut u64 {
+        &mut self.login_backoff_max_secs
+    }
+
+    pub fn password_reset_ttl_secs_mut(&mut self) -> &mut i64 {
+        &mut self.password_reset_ttl_secs
+    }
+
+    pub fn notifier_sink_mut(&mut self) -> &mut String {
+        &mut self.notifier_sink
+    }
+
+    pub fn notifier_path_mut(&mut self) -> &mut Option<String> {
+        &mut self.notifier_path
+    }
+
+    pub fn max_sessions_per_account_mut(&mut self) -> &mut usize {
+        &mut self.max_sessions_per_account
+    }
+
+    pub fn session_limit_policy_mut(&mut self) -> &mut String {
+        &mut self.session_limit_policy
+    }
+
+    pub fn guest_ttl_secs_mut(&mut self) -> &mut i64 {
+        &mut self.guest_ttl_secs
+    }
+
+    pub fn guest_starting_balance_mut(&mut self) -> &mut Decimal {
+        &mut self.guest_starting_balance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    /// Loads a config from an ini file holding `server_section` as its `[server]` section.
+    fn load(server_section: &str) -> Result<Config, anyhow::Error> {
+        let path = std::env::temp_dir().join(format!("ro-rust-config-{}.ini", Uuid::new_v4()));
+        std::fs::write(&path, format!("[server]\n{}\n", server_section)).unwrap();
+        let config = Config::from_file(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        config
+    }
+
+    #[test]
+    fn accepts_a_minimal_config_with_defaults() {
+        let config = load("address = 127.0.0.1\nport = 8080").unwrap();
+        assert_eq!(config.server_port, 8080);
+        assert_eq!(config.ping_interval_secs, 30);
+        assert_eq!(config.idle_timeout_secs, 90);
+    }
+
+    #[test]
+    fn rejects_a_zero_ping_interval() {
+        let error = load("address = 127.0.0.1\nport = 8080\nping_interval_secs = 0").unwrap_err();
+        assert!(error.to_string().contains("ping_interval_secs"));
+    }
+
+    #[test]
+    fn rejects_a_zero_idle_timeout() {
+        let error = load("address = 127.0.0.1\nport = 8080\nidle_timeout_secs = 0").unwrap_err();
+        assert!(error.to_string().contains("idle_timeout_secs"));
+    }
 }
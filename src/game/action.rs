@@ -0,0 +1,239 @@
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use ts_rs::TS;
+
+use crate::game::poker::{PokerAction, PokerEvent};
+use crate::game::roulette::{RouletteAction, RouletteEvent};
+use crate::game::{ActionError, GameType};
+use crate::message::ErrorCode;
+
+/// A game action, decoded for the game of the room it is meant for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameAction {
+    Roulette(RouletteAction),
+    Poker(PokerAction),
+}
+
+/// What a room made of an action, pushed to the player as the state of a game update.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema, TS)]
+#[serde(untagged)]
+pub enum GameEvent {
+    Roulette(RouletteEvent),
+    Poker(PokerEvent),
+}
+
+impl GameAction {
+    /// Decodes a client's `action` and `params` as an action of `game_type` and validates
+    /// it, so that rooms only ever see well-formed actions.
+    pub fn decode(
+        game_type: &GameType,
+        action: &str,
+        params: Value,
+    ) -> Result<GameAction, ActionError> {
+        match game_type {
+            GameType::Roulette(variant) => {
+                let action: RouletteAction = decode_tagged(action, params, RouletteAction::NAMES)?;
+                action.validate(variant)?;
+                Ok(GameAction::Roulette(action))
+            }
+            GameType::Poker(_) => {
+                let action: PokerAction = decode_tagged(action, params, PokerAction::NAMES)?;
+                action.validate()?;
+                Ok(GameAction::Poker(action))
+            }
+            _ => Err(ActionError::new(
+                ErrorCode::InvalidAction,
+                "The game takes no actions",
+            )),
+        }
+    }
+}
+
+/// Deserializes an action enum tagged with `action` and `params`. Unknown actions are
+/// reported with the list of known ones, malformed params with the field and the kind of
+/// problem. serde's own message is not passed on, as it quotes the values it choked on.
+fn decode_tagged<T: DeserializeOwned>(
+    action: &str,
+    params: Value,
+    names: &[&str],
+) -> Result<T, ActionError> {
+    if !names.contains(&action) {
+        return Err(ActionError {
+            details: Some(json!({ "action": action, "expected": names })),
+            ..ActionError::new(ErrorCode::InvalidAction, "Unknown action")
+        });
+    }
+
+    // Actions without params may send `null` or `{}` instead of leaving them out
+    let mut tagged = json!({ "action": action });
+    if !params.is_null() && params != json!({}) {
+        tagged["params"] = params;
+    }
+
+    serde_path_to_error::deserialize(tagged).map_err(|e| {
+        let message = e.inner().to_string();
+        let category = PARAM_ERROR_CATEGORIES
+            .iter()
+            .find(|(prefix, _)| message.starts_with(prefix))
+            .map_or("invalid", |(_, category)| category);
+
+        // Missing fields are reported at the struct that lacks them, and their name is ours
+        let mut field = e.path().to_string();
+        if let Some(missing) = message
+            .strip_prefix("missing field `")
+            .and_then(|rest| rest.split('`').next())
+        {
+            field = match e.path().iter().next() {
+                Some(_) => format!("{}.{}", field, missing),
+                None => missing.to_string(),
+            };
+        }
+
+        ActionError {
+            details: Some(json!({ "action": action, "field": field, "category": category })),
+            ..ActionError::new(ErrorCode::InvalidParams, "Invalid action params")
+        }
+    })
+}
+
+/// serde's error messages by their start, and how they are reported to clients.
+const PARAM_ERROR_CATEGORIES: &[(&str, &str)] = &[
+    ("missing field", "missing_field"),
+    ("unknown field", "unknown_field"),
+    ("duplicate field", "duplicate_field"),
+    ("unknown variant", "unknown_variant"),
+    ("invalid type", "invalid_type"),
+    ("invalid value", "invalid_value"),
+    ("invalid length", "invalid_length"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::game_types::{PokerVariant, RouletteVariant, SlotsVariant};
+    use rust_decimal::Decimal;
+
+    const EUROPEAN: GameType = GameType::Roulette(RouletteVariant::European);
+    const HOLDEM: GameType = GameType::Poker(PokerVariant::TexasHoldem);
+
+    fn decode_error(game_type: &GameType, action: &str, params: Value) -> ActionError {
+        GameAction::decode(game_type, action, params).unwrap_err()
+    }
+
+    #[test]
+    fn decodes_roulette_actions() {
+        let action = GameAction::decode(
+            &EUROPEAN,
+            "place_bet",
+            json!({ "bet": { "straight": 17 }, "amount": "2.50" }),
+        );
+        assert_eq!(
+            action,
+            Ok(GameAction::Roulette(RouletteAction::PlaceBet {
+                bet: serde_json::from_value(json!({ "straight": 17 })).unwrap(),
+                amount: Decimal::new(250, 2),
+            }))
+        );
+
+        for params in [Value::Null, json!({})] {
+            assert_eq!(
+                GameAction::decode(&EUROPEAN, "spin", params),
+                Ok(GameAction::Roulette(RouletteAction::Spin))
+            );
+        }
+    }
+
+    #[test]
+    fn decodes_poker_actions() {
+        assert_eq!(
+            GameAction::decode(&HOLDEM, "raise", json!({ "amount": 20 })),
+            Ok(GameAction::Poker(PokerAction::Raise {
+                amount: Decimal::from(20),
+            }))
+        );
+        assert_eq!(
+            GameAction::decode(&HOLDEM, "all_in", Value::Null),
+            Ok(GameAction::Poker(PokerAction::AllIn))
+        );
+    }
+
+    #[test]
+    fn rejects_actions_of_another_game() {
+        let error = decode_error(&HOLDEM, "spin", Value::Null);
+        assert_eq!(error.code, ErrorCode::InvalidAction);
+        assert_eq!(
+            error.details,
+            Some(json!({ "action": "spin", "expected": PokerAction::NAMES }))
+        );
+    }
+
+    #[test]
+    fn reports_malformed_params_without_their_values() {
+        let error = decode_error(
+            &EUROPEAN,
+            "place_bet",
+            json!({ "bet": "red", "amount": "<script>" }),
+        );
+        assert_eq!(error.code, ErrorCode::InvalidParams);
+        assert_eq!(
+            error.details,
+            Some(json!({
+                "action": "place_bet",
+                "field": "params.amount",
+                "category": "invalid_value",
+            }))
+        );
+
+        let error = decode_error(&HOLDEM, "bet", json!({ "amount": null }));
+        assert_eq!(
+            error.details,
+            Some(json!({ "action": "bet", "field": "params.amount", "category": "invalid_type" }))
+        );
+
+        let error = decode_error(&HOLDEM, "bet", json!({}));
+        assert_eq!(
+            error.details,
+            Some(json!({ "action": "bet", "field": "params", "category": "missing_field" }))
+        );
+
+        let error = decode_error(&EUROPEAN, "place_bet", json!({ "bet": "red" }));
+        assert_eq!(
+            error.details,
+            Some(json!({
+                "action": "place_bet",
+                "field": "params.amount",
+                "category": "missing_field",
+            }))
+        );
+    }
+
+    #[test]
+    fn validates_decoded_actions() {
+        let double_zero = json!({ "bet": "double_zero", "amount": "1" });
+        assert_eq!(
+            decode_error(&EUROPEAN, "place_bet", double_zero).code,
+            ErrorCode::InvalidParams
+        );
+        let american = GameType::Roulette(RouletteVariant::American);
+        let double_zero = json!({ "bet": "double_zero", "amount": "1" });
+        assert!(GameAction::decode(&american, "place_bet", double_zero).is_ok());
+
+        let error = decode_error(&HOLDEM, "bet", json!({ "amount": "-5" }));
+        assert_eq!(error.code, ErrorCode::InvalidParams);
+        assert_eq!(
+            error.details,
+            Some(json!({ "field": "amount", "reason": "must be positive" }))
+        );
+    }
+
+    #[test]
+    fn games_without_actions_take_none() {
+        let slots = GameType::Slots(SlotsVariant::SlotMachines);
+        assert_eq!(
+            decode_error(&slots, "spin", Value::Null).code,
+            ErrorCode::InvalidAction
+        );
+    }
+}
@@ -0,0 +1,364 @@
+use serde_json::{json, Value};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::game::{
+    ActionError, DisconnectReason, GameAction, GameEvent, GameType, Room, RoomSettings, RoomSummary,
+};
+use crate::message::ErrorCode;
+
+/// Commands a room can queue before senders have to wait.
+const ROOM_MAILBOX_SIZE: usize = 64;
+
+/// What a room's task can be asked to do. Commands that need an answer carry the sender
+/// the room replies on.
+enum RoomCommand {
+    /// Seats a player, replying `false` if the room is full or already closed.
+    AddPlayer {
+        player_id: String,
+        reply: oneshot::Sender<bool>,
+    },
+    /// Takes a player out of the room, replying `true` if that left the room empty and
+    /// it closed.
+    RemovePlayer {
+        player_id: String,
+        reason: DisconnectReason,
+        reply: oneshot::Sender<bool>,
+    },
+    Action {
+        player_id: String,
+        action: GameAction,
+        reply: oneshot::Sender<Result<GameEvent, ActionError>>,
+    },
+    Broadcast {
+        update: Value,
+    },
+    Summary {
+        reply: oneshot::Sender<RoomSummary>,
+    },
+    RoundInProgress {
+        reply: oneshot::Sender<bool>,
+    },
+    /// Voids the running round, replying whether there was one.
+    VoidRound {
+        reply: oneshot::Sender<bool>,
+    },
+}
+
+/// Mailbox of a room running in its own task, with the parts of the room that never change
+/// so that matchmaking does not have to ask the room for them.
+#[derive(Clone)]
+pub struct RoomHandle {
+    id: String,
+    game_type: GameType,
+    settings: RoomSettings,
+    commands: mpsc::Sender<RoomCommand>,
+}
+
+/// Moves a room into its own task, which owns the room's state and handles its commands one
+/// at a time. Rooms never wait on each other, so a slow table only delays its own players.
+///
+/// The task ends once the room has no players left, or when every handle is dropped.
+pub fn spawn_room(mut room: Box<dyn Room + Send + Sync>) -> RoomHandle {
+    let (commands, mut mailbox) = mpsc::channel(ROOM_MAILBOX_SIZE);
+    let handle = RoomHandle {
+        id: room.id().to_string(),
+        game_type: room.game_type(),
+        settings: room.settings().clone(),
+        commands,
+    };
+
+    tokio::spawn(async move {
+        while let Some(command) = mailbox.recv().await {
+            match command {
+                RoomCommand::AddPlayer { player_id, reply } => {
+                    let seated = !room.is_full();
+                    if seated {
+                        room.add_player(player_id.clone());
+                        room.broadcast(
+                            &json!({ "event": "player_joined", "player_id": player_id }),
+                        );
+                    }
+                    let _ = reply.send(seated);
+                }
+                RoomCommand::RemovePlayer {
+                    player_id,
+                    reason,
+                    reply,
+                } => {
+                    println!("{} left room {}: {}", player_id, room.id(), reason);
+                    room.remove_player(player_id.clone());
+                    if room.is_empty() {
+                        let _ = reply.send(true);
+                        break;
+                    }
+                    room.broadcast(&json!({
+                        "event": "player_left",
+                        "player_id": player_id,
+                        "reason": reason,
+                    }));
+                    let _ = reply.send(false);
+                }
+                RoomCommand::Action {
+                    player_id,
+                    action,
+                    reply,
+                } => {
+                    // The connection may still name a room its player has since left
+                    if !room.has_player(&player_id) {
+                        let _ = reply.send(Err(ActionError::new(
+                            ErrorCode::NotInRoom,
+                            "Not seated in this room",
+                        )));
+                        continue;
+                    }
+
+                    let result = room.handle_action(player_id.clone(), action);
+                    if let Ok(event) = &result {
+                        match serde_json::to_value(event) {
+                            Ok(update) => room.broadcast_except(&player_id, &update),
+                            Err(e) => eprintln!("Error encoding game event: {}", e),
+                        }
+                    }
+                    let _ = reply.send(result);
+                }
+                RoomCommand::Broadcast { update } => room.broadcast(&update),
+                RoomCommand::Summary { reply } => {
+                    let _ = reply.send(RoomSummary {
+                        room_id: room.id().to_string(),
+                        game_type: room.game_type().to_db_string().to_string(),
+                        private: room.settings().private,
+                        high_limit: room.settings().high_limit,
+                        free_play: room.settings().free_play,
+                        players: room.player_count(),
+                        full: room.is_full(),
+                    });
+                }
+                RoomCommand::RoundInProgress { reply } => {
+                    let _ = reply.send(room.round_in_progress());
+                }
+                RoomCommand::VoidRound { reply } => {
+                    let running = room.round_in_progress();
+                    if running {
+                        room.void_round();
+                    }
+                    let _ = reply.send(running);
+                }
+            }
+        }
+    });
+
+    handle
+}
+
+impl RoomHandle {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn game_type(&self) -> &GameType {
+        &self.game_type
+    }
+
+    pub fn settings(&self) -> &RoomSettings {
+        &self.settings
+    }
+
+    /// Sends a command and waits for the room's answer. Returns `None` if the room closed
+    /// before answering.
+    async fn request<T>(
+        &self,
+        command: impl FnOnce(oneshot::Sender<T>) -> RoomCommand,
+    ) -> Option<T> {
+        let (reply, answer) = oneshot::channel();
+        self.commands.send(command(reply)).await.ok()?;
+        answer.await.ok()
+    }
+
+    pub async fn add_player(&self, player_id: String) -> bool {
+        self.request(|reply| RoomCommand::AddPlayer { player_id, reply })
+            .await
+            .unwrap_or(false)
+    }
+
+    /// Returns `true` if the room is now empty and closed.
+    pub async fn remove_player(&self, player_id: String, reason: DisconnectReason) -> bool {
+        self.request(|reply| RoomCommand::RemovePlayer {
+            player_id,
+            reason,
+            reply,
+        })
+        .await
+        .unwrap_or(true)
+    }
+
+    pub async fn handle_action(
+        &self,
+        player_id: String,
+        action: GameAction,
+    ) -> Option<Result<GameEvent, ActionError>> {
+        self.request(|reply| RoomCommand::Action {
+            player_id,
+            action,
+            reply,
+        })
+        .await
+    }
+
+    pub async fn broadcast(&self, update: Value) {
+        let _ = self.commands.send(RoomCommand::Broadcast { update }).await;
+    }
+
+    pub async fn summary(&self) -> Option<RoomSummary> {
+        self.request(|reply| RoomCommand::Summary { reply }).await
+    }
+
+    pub async fn round_in_progress(&self) -> bool {
+        self.request(|reply| RoomCommand::RoundInProgress { reply })
+            .await
+            .unwrap_or(false)
+    }
+
+    pub async fn void_round(&self) -> bool {
+        self.request(|reply| RoomCommand::VoidRound { reply })
+            .await
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::game_types::RouletteVariant;
+    use crate::game::roulette::RouletteRoom;
+    use crate::game::{Outbox, RouletteAction};
+    use std::sync::{Arc, Mutex};
+
+    /// Collects the updates rooms push, by player.
+    #[derive(Default)]
+    struct RecordingOutbox {
+        updates: Mutex<Vec<(String, Value)>>,
+    }
+
+    impl RecordingOutbox {
+        fn events_of(&self, player_id: &str) -> Vec<String> {
+            self.updates
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(player, _)| player == player_id)
+                .filter_map(|(_, update)| update["event"].as_str().map(str::to_string))
+                .collect()
+        }
+    }
+
+    impl Outbox for RecordingOutbox {
+        fn send_to_player(&self, _room_id: &str, player_id: &str, update: &Value) {
+            self.updates
+                .lock()
+                .unwrap()
+                .push((player_id.to_string(), update.clone()));
+        }
+    }
+
+    fn roulette_room(outbox: &Arc<RecordingOutbox>) -> RoomHandle {
+        spawn_room(Box::new(RouletteRoom::new(
+            "room".to_string(),
+            GameType::Roulette(RouletteVariant::European),
+            RoomSettings::default(),
+            Arc::clone(outbox) as Arc<dyn Outbox>,
+        )))
+    }
+
+    fn spin() -> GameAction {
+        GameAction::Roulette(RouletteAction::Spin)
+    }
+
+    #[tokio::test]
+    async fn seats_players_until_the_room_is_full() {
+        let outbox = Arc::new(RecordingOutbox::default());
+        let room = roulette_room(&outbox);
+        for n in 0..6 {
+            assert!(room.add_player(format!("player-{}", n)).await);
+        }
+        assert!(!room.add_player("late".to_string()).await);
+
+        let summary = room.summary().await.unwrap();
+        assert_eq!(summary.players, 6);
+        assert!(summary.full);
+        assert_eq!(outbox.events_of("player-0").len(), 6);
+        assert!(outbox.events_of("late").is_empty());
+    }
+
+    #[tokio::test]
+    async fn sends_the_outcome_of_an_action_to_the_other_players() {
+        let outbox = Arc::new(RecordingOutbox::default());
+        let room = roulette_room(&outbox);
+        room.add_player("alice".to_string()).await;
+        room.add_player("bob".to_string()).await;
+
+        let event = room
+            .handle_action("alice".to_string(), spin())
+            .await
+            .unwrap();
+        assert!(event.is_ok());
+        // The acting player gets the event as the reply instead
+        assert_eq!(
+            outbox.events_of("alice"),
+            vec!["player_joined", "player_joined"]
+        );
+        assert_eq!(
+            outbox.events_of("bob"),
+            vec!["player_joined", "spin_requested"]
+        );
+    }
+
+    #[tokio::test]
+    async fn closes_once_the_last_player_leaves() {
+        let outbox = Arc::new(RecordingOutbox::default());
+        let room = roulette_room(&outbox);
+        room.add_player("alice".to_string()).await;
+
+        assert!(
+            room.remove_player("alice".to_string(), DisconnectReason::Left)
+                .await
+        );
+        assert!(!room.add_player("bob".to_string()).await);
+        assert!(room.summary().await.is_none());
+        assert!(room
+            .handle_action("bob".to_string(), spin())
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn a_player_who_left_can_no_longer_act() {
+        let outbox = Arc::new(RecordingOutbox::default());
+        let room = roulette_room(&outbox);
+        assert!(room.add_player("alice".to_string()).await);
+        assert!(room.add_player("bob".to_string()).await);
+
+        assert!(room
+            .handle_action("alice".to_string(), spin())
+            .await
+            .unwrap()
+            .is_ok());
+        assert!(
+            !room
+                .remove_player("alice".to_string(), DisconnectReason::Left)
+                .await
+        );
+
+        // Neither the player who left nor whoever the socket continues as may play on
+        let left = room
+            .handle_action("alice".to_string(), spin())
+            .await
+            .unwrap();
+        assert_eq!(left.unwrap_err().code, ErrorCode::NotInRoom);
+        let other = room
+            .handle_action("carol".to_string(), spin())
+            .await
+            .unwrap();
+        assert_eq!(other.unwrap_err().code, ErrorCode::NotInRoom);
+        assert!(outbox.events_of("carol").is_empty());
+    }
+}
@@ -1,6 +1,8 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use ts_rs::TS;
 
-#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize, JsonSchema, TS)]
 pub enum GameType {
     Roulette(RouletteVariant),
     CardGame(CardGameVariant),
@@ -15,7 +17,7 @@ pub enum GameType {
     Other(OtherGameVariant),
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize, JsonSchema, TS)]
 
 pub enum RouletteVariant {
     American,
@@ -23,7 +25,7 @@ pub enum RouletteVariant {
     French,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize, JsonSchema, TS)]
 
 pub enum CardGameVariant {
     Blackjack,
@@ -38,7 +40,7 @@ pub enum CardGameVariant {
     Spanish21,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize, JsonSchema, TS)]
 
 pub enum PokerVariant {
     TexasHoldem,
@@ -48,7 +50,7 @@ pub enum PokerVariant {
     VideoPoker,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize, JsonSchema, TS)]
 
 pub enum SlotsVariant {
     SlotMachines,
@@ -56,7 +58,7 @@ pub enum SlotsVariant {
     ProgressiveSlots,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize, JsonSchema, TS)]
 
 pub enum LotteryVariant {
     SiciwinLottery,
@@ -66,14 +68,14 @@ pub enum LotteryVariant {
     FastLottery,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize, JsonSchema, TS)]
 
 pub enum RacingVariant {
     HorseRacing,
     DogRacing,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize, JsonSchema, TS)]
 
 pub enum DiceVariant {
     Craps,
@@ -81,14 +83,14 @@ pub enum DiceVariant {
     SiciwinDice,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize, JsonSchema, TS)]
 
 pub enum TableGameVariant {
     BigSixWheel,
     WheelOfFortune,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize, JsonSchema, TS)]
 
 pub enum AsianGameVariant {
     PaiGowTiles,
@@ -97,13 +99,13 @@ pub enum AsianGameVariant {
     Fantan,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize, JsonSchema, TS)]
 
 pub enum SiciwinGameVariant {
     SiciwinGames,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize, JsonSchema, TS)]
 
 pub enum OtherGameVariant {
     SportswinBetting,
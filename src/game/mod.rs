@@ -1,64 +1,179 @@
+mod action;
+mod actor;
 pub mod game_types;
+mod outbox;
 mod player;
 mod poker;
 mod room;
 mod roulette;
 
+use serde::Serialize;
 use serde_json::json;
 use std::{collections::HashMap, sync::Arc};
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
 use crate::db::DbPool;
+use crate::message::ErrorCode;
 use crate::queues::db_queue::DbQueue;
+use actor::RoomHandle;
+use game_types::{PokerVariant, RouletteVariant};
 
+pub use action::{GameAction, GameEvent};
 pub use game_types::GameType;
-pub use room::Room;
+pub use outbox::Outbox;
+pub use poker::PokerAction;
+pub use room::{ActionError, DisconnectReason, Room, RoomSettings};
+pub use roulette::RouletteAction;
 
+/// A room as listed in the lobby and in metrics.
+#[derive(Debug, Serialize)]
+pub struct RoomSummary {
+    pub room_id: String,
+    pub game_type: String,
+    pub private: bool,
+    pub high_limit: bool,
+    pub free_play: bool,
+    pub players: usize,
+    pub full: bool,
+}
+
+/// Routes players and their actions to rooms. Every room runs in its own task (see
+/// `actor::spawn_room`), so the manager itself only keeps the room handles.
 pub struct GameManager {
-    rooms: RwLock<HashMap<String, Box<dyn Room + Send + Sync>>>,
+    rooms: RwLock<HashMap<String, RoomHandle>>,
     db_pool: Arc<DbPool>,
     db_queue: Arc<DbQueue>,
+    outbox: Arc<dyn Outbox>,
 }
 
 impl GameManager {
-    pub fn new(db_pool: Arc<DbPool>, db_queue: Arc<DbQueue>) -> Self {
+    pub fn new(db_pool: Arc<DbPool>, db_queue: Arc<DbQueue>, outbox: Arc<dyn Outbox>) -> Self {
         GameManager {
             rooms: RwLock::new(HashMap::new()),
             db_pool,
             db_queue,
+            outbox,
         }
     }
 
-    pub async fn assign_to_room(&self, player_id: String, game_type: GameType) -> String {
-        let mut rooms = self.rooms.write().await;
-        let room = rooms
-            .values_mut()
-            .find(|r| r.game_type() == game_type && !r.is_full());
+    /// Seats a player at an open public room with matching game type and settings,
+    /// opening a new room if none has a free seat. Since settings must match exactly,
+    /// free-play and real-money players never share a room.
+    pub async fn assign_to_room(
+        &self,
+        player_id: String,
+        game_type: GameType,
+        settings: RoomSettings,
+    ) -> Result<String, ActionError> {
+        let candidates: Vec<RoomHandle> = self
+            .rooms
+            .read()
+            .await
+            .values()
+            .filter(|r| {
+                r.game_type() == &game_type && r.settings() == &settings && !r.settings().private
+            })
+            .cloned()
+            .collect();
 
-        match room {
-            Some(room) => {
-                room.add_player(player_id);
-                room.id().to_string()
+        // Rooms decide themselves whether they still have a seat, as they may have filled
+        // up or closed since the list was taken
+        for room in candidates {
+            if room.add_player(player_id.clone()).await {
+                return Ok(room.id().to_string());
             }
-            None => {
-                let room_id = Uuid::new_v4().to_string();
-
-                let new_room: Box<dyn Room + Send + Sync> = match game_type {
-                    GameType::Poker(_) => {
-                        Box::new(poker::PokerRoom::new(room_id.clone(), game_type.clone()))
-                    }
-                    GameType::Roulette(_) => Box::new(roulette::RouletteRoom::new(
-                        room_id.clone(),
-                        game_type.clone(),
-                    )),
-                    _ => panic!("Unsupported game type"),
-                };
-                // new_room.add_player(player_id); //TODO: Implement this method
-                rooms.insert(room_id.clone(), new_room);
-                room_id
+        }
+
+        let room_id = Uuid::new_v4().to_string();
+        let mut new_room = self.create_room(room_id.clone(), game_type, settings)?;
+        new_room.add_player(player_id);
+        self.rooms
+            .write()
+            .await
+            .insert(room_id.clone(), actor::spawn_room(new_room));
+        Ok(room_id)
+    }
+
+    /// Opens a private room for the player. Private rooms are never matched by
+    /// `assign_to_room`.
+    pub async fn create_private_room(
+        &self,
+        player_id: String,
+        game_type: GameType,
+    ) -> Result<String, ActionError> {
+        let room_id = Uuid::new_v4().to_string();
+        let settings = RoomSettings {
+            private: true,
+            ..RoomSettings::default()
+        };
+
+        let mut new_room = self.create_room(room_id.clone(), game_type, settings)?;
+        new_room.add_player(player_id);
+        self.rooms
+            .write()
+            .await
+            .insert(room_id.clone(), actor::spawn_room(new_room));
+        Ok(room_id)
+    }
+
+    /// Game types that rooms can be opened for.
+    pub fn playable_game_types() -> Vec<GameType> {
+        vec![
+            GameType::Roulette(RouletteVariant::American),
+            GameType::Roulette(RouletteVariant::European),
+            GameType::Roulette(RouletteVariant::French),
+            GameType::Poker(PokerVariant::TexasHoldem),
+            GameType::Poker(PokerVariant::Omaha),
+            GameType::Poker(PokerVariant::SevenCardStud),
+            GameType::Poker(PokerVariant::CaribbeanStud),
+            GameType::Poker(PokerVariant::VideoPoker),
+        ]
+    }
+
+    pub async fn room_summaries(&self) -> Vec<RoomSummary> {
+        let mut summaries = Vec::new();
+        for room in self.room_handles().await {
+            if let Some(summary) = room.summary().await {
+                summaries.push(summary);
             }
         }
+        summaries.sort_by(|a, b| a.game_type.cmp(&b.game_type));
+        summaries
+    }
+
+    fn create_room(
+        &self,
+        room_id: String,
+        game_type: GameType,
+        settings: RoomSettings,
+    ) -> Result<Box<dyn Room + Send + Sync>, ActionError> {
+        match game_type {
+            GameType::Poker(_) => Ok(Box::new(poker::PokerRoom::new(
+                room_id,
+                game_type,
+                settings,
+                Arc::clone(&self.outbox),
+            ))),
+            GameType::Roulette(_) => Ok(Box::new(roulette::RouletteRoom::new(
+                room_id,
+                game_type,
+                settings,
+                Arc::clone(&self.outbox),
+            ))),
+            _ => Err(ActionError {
+                details: Some(json!({ "game_type": game_type.to_db_string() })),
+                ..ActionError::new(ErrorCode::InvalidGameType, "Game type is not playable")
+            }),
+        }
+    }
+
+    async fn room(&self, room_id: &str) -> Option<RoomHandle> {
+        self.rooms.read().await.get(room_id).cloned()
+    }
+
+    async fn room_handles(&self) -> Vec<RoomHandle> {
+        self.rooms.read().await.values().cloned().collect()
     }
 
     pub async fn handle_action(
@@ -67,22 +182,59 @@ impl GameManager {
         player_id: String,
         action: String,
         params: serde_json::Value,
-    ) -> serde_json::Value {
-        let rooms = self.rooms.read().await;
-        if let Some(room) = rooms.get(&room_id) {
-            room.handle_action(player_id, action, params)
-        } else {
-            json!({ "error": "Room not found" })
+    ) -> Result<GameEvent, ActionError> {
+        let room_not_found = || ActionError {
+            details: Some(json!({ "room_id": room_id })),
+            ..ActionError::new(ErrorCode::RoomNotFound, "Room not found")
+        };
+        let room = self.room(&room_id).await.ok_or_else(room_not_found)?;
+
+        let action = GameAction::decode(room.game_type(), &action, params)?;
+        room.handle_action(player_id, action)
+            .await
+            .unwrap_or_else(|| Err(room_not_found()))
+    }
+
+    pub async fn broadcast_to_room(&self, room_id: &str, update: &serde_json::Value) {
+        if let Some(room) = self.room(room_id).await {
+            room.broadcast(update.clone()).await;
+        }
+    }
+
+    pub async fn rounds_in_progress(&self) -> usize {
+        let mut running = 0;
+        for room in self.room_handles().await {
+            if room.round_in_progress().await {
+                running += 1;
+            }
         }
+        running
     }
 
-    pub async fn remove_player(&self, room_id: String, player_id: String) {
-        let mut rooms = self.rooms.write().await;
-        if let Some(room) = rooms.get_mut(&room_id) {
-            room.remove_player(player_id);
-            if room.is_empty() {
-                rooms.remove(&room_id);
+    /// Voids every round that is still running and returns how many there were.
+    pub async fn void_rounds(&self) -> usize {
+        let mut voided = 0;
+        for room in self.room_handles().await {
+            if room.void_round().await {
+                voided += 1;
             }
         }
+        voided
+    }
+
+    /// Takes a player out of their room, closing the room once it is empty. The remaining
+    /// players are told why the player left.
+    pub async fn remove_player(
+        &self,
+        room_id: String,
+        player_id: String,
+        reason: DisconnectReason,
+    ) {
+        let Some(room) = self.room(&room_id).await else {
+            return;
+        };
+        if room.remove_player(player_id, reason).await {
+            self.rooms.write().await.remove(&room_id);
+        }
     }
 }
@@ -0,0 +1,9 @@
+use serde_json::Value;
+
+/// Delivers game updates to connected players, so rooms and the `GameManager` can push
+/// state without holding any socket.
+pub trait Outbox: Send + Sync {
+    /// Queues a game update of a room for a player. Updates for players without a
+    /// connection are only kept for replay when they resume.
+    fn send_to_player(&self, room_id: &str, player_id: &str, update: &Value);
+}
@@ -0,0 +1,67 @@
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::game::ActionError;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, JsonSchema, TS)]
+#[serde(tag = "action", content = "params", rename_all = "snake_case")]
+pub enum PokerAction {
+    Fold,
+    Check,
+    Call,
+    Bet {
+        #[ts(type = "string")]
+        amount: Decimal,
+    },
+    /// Raises the current bet to `amount`.
+    Raise {
+        #[ts(type = "string")]
+        amount: Decimal,
+    },
+    AllIn,
+}
+
+impl PokerAction {
+    pub const NAMES: &'static [&'static str] = &["fold", "check", "call", "bet", "raise", "all_in"];
+
+    /// Checks what the types cannot express: stakes have to be positive.
+    pub fn validate(&self) -> Result<(), ActionError> {
+        match self {
+            PokerAction::Bet { amount } | PokerAction::Raise { amount }
+                if *amount <= Decimal::ZERO =>
+            {
+                Err(ActionError::invalid_param("amount", "must be positive"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema, TS)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum PokerEvent {
+    Folded {
+        player_id: String,
+    },
+    Checked {
+        player_id: String,
+    },
+    Called {
+        player_id: String,
+    },
+    BetPlaced {
+        player_id: String,
+        #[ts(type = "string")]
+        amount: Decimal,
+    },
+    Raised {
+        player_id: String,
+        #[ts(type = "string")]
+        amount: Decimal,
+    },
+    WentAllIn {
+        player_id: String,
+    },
+}
@@ -1,3 +1,6 @@
+mod action;
 mod game;
 
+pub use action::{PokerAction, PokerEvent};
+
 pub use game::PokerRoom;
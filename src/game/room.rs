@@ -1,17 +1,73 @@
-use crate::game::GameType;
-use async_trait::async_trait;
-use serde_json::Value;
+use crate::game::{GameAction, GameEvent, GameType};
+use crate::message::{ErrorCode, ServerMessage};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fmt;
 
-#[async_trait]
 pub trait Room: Send + Sync {
     fn id(&self) -> &str;
     fn game_type(&self) -> GameType;
+    fn settings(&self) -> &RoomSettings;
     fn add_player(&mut self, player_id: String);
     fn remove_player(&mut self, player_id: String);
+    fn has_player(&self, player_id: &str) -> bool;
     fn is_full(&self) -> bool;
+    fn player_count(&self) -> usize;
     fn is_empty(&self) -> bool;
-    fn handle_action(&self, player_id: String, action: String, params: Value) -> Value;
-    async fn broadcast(&self, message: &str);
+    /// Plays an action that was already decoded and validated for the room's game.
+    fn handle_action(
+        &self,
+        player_id: String,
+        action: GameAction,
+    ) -> Result<GameEvent, ActionError>;
+    /// Pushes a game update to every player in the room.
+    fn broadcast(&self, update: &Value);
+    /// Pushes a game update to every player in the room but `player_id`, e.g. the outcome
+    /// of an action that its player already got as the reply.
+    fn broadcast_except(&self, player_id: &str, update: &Value);
+    /// Whether a round is being played that would be lost if the room closed now.
+    fn round_in_progress(&self) -> bool;
+    /// Cancels the running round without settling it, e.g. when the server shuts down.
+    fn void_round(&mut self);
+}
+
+/// An action that was turned down, sent back to the player as an `Error`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionError {
+    pub code: ErrorCode,
+    pub message: String,
+    /// Values the client needs to word the error, e.g. the stake and the balance that fell
+    /// short of it.
+    pub details: Option<Value>,
+}
+
+impl ActionError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        ActionError {
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    /// An action param that decoded fine but has a value the game does not allow.
+    pub fn invalid_param(field: &str, reason: &str) -> Self {
+        ActionError {
+            code: ErrorCode::InvalidParams,
+            message: format!("Invalid {}: {}", field, reason),
+            details: Some(json!({ "field": field, "reason": reason })),
+        }
+    }
+}
+
+impl From<ActionError> for ServerMessage {
+    fn from(error: ActionError) -> Self {
+        ServerMessage::Error {
+            code: error.code,
+            message: error.message,
+            details: error.details,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -19,3 +75,35 @@ pub enum RoomCapacity {
     Default,
     Custom(u8),
 }
+
+/// Table options that decide which players a room may be matched with.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct RoomSettings {
+    /// Private rooms are only joined by invitation and never handed out by matchmaking.
+    pub private: bool,
+    pub high_limit: bool,
+    /// Play-money table. Guests may only sit at free-play tables.
+    pub free_play: bool,
+}
+
+/// Why a player left their room, sent to the remaining players and logged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisconnectReason {
+    /// The player left the room, e.g. because a guest was upgraded to an account.
+    Left,
+    /// The socket closed and the player did not resume within the grace period.
+    ResumeExpired,
+    /// The socket stopped answering heartbeats.
+    IdleTimeout,
+}
+
+impl fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisconnectReason::Left => write!(f, "left"),
+            DisconnectReason::ResumeExpired => write!(f, "resume grace period expired"),
+            DisconnectReason::IdleTimeout => write!(f, "idle timeout"),
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::game::game_types::RouletteVariant;
+use crate::game::ActionError;
+
+/// A roulette bet, e.g. `"red"` or `{"straight": 17}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum RouletteBet {
+    /// A single number from 0 to 36.
+    Straight(u8),
+    /// The 00 pocket, American wheels only.
+    DoubleZero,
+    Red,
+    Black,
+    Odd,
+    Even,
+    /// 1 to 18.
+    Low,
+    /// 19 to 36.
+    High,
+    /// First, second or third dozen, numbered 1 to 3.
+    Dozen(u8),
+    /// First, second or third column, numbered 1 to 3.
+    Column(u8),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, JsonSchema, TS)]
+#[serde(tag = "action", content = "params", rename_all = "snake_case")]
+pub enum RouletteAction {
+    PlaceBet {
+        bet: RouletteBet,
+        #[ts(type = "string")]
+        amount: Decimal,
+    },
+    ClearBets,
+    Spin,
+}
+
+impl RouletteAction {
+    pub const NAMES: &'static [&'static str] = &["place_bet", "clear_bets", "spin"];
+
+    /// Checks what the types cannot express: positive stakes and numbers that exist on
+    /// the wheel.
+    pub fn validate(&self, variant: &RouletteVariant) -> Result<(), ActionError> {
+        let RouletteAction::PlaceBet { bet, amount } = self else {
+            return Ok(());
+        };
+        if *amount <= Decimal::ZERO {
+            return Err(ActionError::invalid_param("amount", "must be positive"));
+        }
+        match bet {
+            RouletteBet::Straight(number) if *number > 36 => Err(ActionError::invalid_param(
+                "bet",
+                "straight bets take a number from 0 to 36",
+            )),
+            RouletteBet::DoubleZero if *variant != RouletteVariant::American => Err(
+                ActionError::invalid_param("bet", "only American wheels have a 00 pocket"),
+            ),
+            RouletteBet::Dozen(n) | RouletteBet::Column(n) if !(1..=3).contains(n) => Err(
+                ActionError::invalid_param("bet", "dozens and columns are numbered 1 to 3"),
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema, TS)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum RouletteEvent {
+    BetPlaced {
+        player_id: String,
+        bet: RouletteBet,
+        #[ts(type = "string")]
+        amount: Decimal,
+    },
+    BetsCleared {
+        player_id: String,
+    },
+    SpinRequested {
+        player_id: String,
+    },
+}
@@ -1,16 +1,18 @@
-use crate::game::{game_types::*, Room};
-use async_trait::async_trait;
+use super::action::{RouletteAction, RouletteEvent};
+use crate::game::{game_types::*, ActionError, GameAction, GameEvent, Outbox, Room, RoomSettings};
+use crate::message::ErrorCode;
 use serde_json::json;
 use std::collections::HashSet;
+use std::sync::Arc;
 
 pub struct RouletteRoom {
     id: String,
     players: HashSet<String>,
     game_type: GameType,
+    settings: RoomSettings,
+    outbox: Arc<dyn Outbox>,
 }
 
-#[async_trait]
-#[allow(unused_variables)]
 impl Room for RouletteRoom {
     fn id(&self) -> &str {
         &self.id
@@ -20,6 +22,10 @@ impl Room for RouletteRoom {
         self.game_type.clone()
     }
 
+    fn settings(&self) -> &RoomSettings {
+        &self.settings
+    }
+
     fn add_player(&mut self, player_id: String) {
         self.players.insert(player_id);
     }
@@ -28,10 +34,18 @@ impl Room for RouletteRoom {
         self.players.remove(&player_id);
     }
 
+    fn has_player(&self, player_id: &str) -> bool {
+        self.players.contains(player_id)
+    }
+
     fn is_full(&self) -> bool {
         self.players.len() >= 6
     }
 
+    fn player_count(&self) -> usize {
+        self.players.len()
+    }
+
     fn is_empty(&self) -> bool {
         self.players.is_empty()
     }
@@ -39,25 +53,63 @@ impl Room for RouletteRoom {
     fn handle_action(
         &self,
         player_id: String,
-        action: String,
-        params: serde_json::Value,
-    ) -> serde_json::Value {
-        json!({})
+        action: GameAction,
+    ) -> Result<GameEvent, ActionError> {
+        let GameAction::Roulette(action) = action else {
+            return Err(ActionError::new(
+                ErrorCode::InvalidAction,
+                "Not a roulette action",
+            ));
+        };
+
+        // Rounds are not played yet, so actions are only acknowledged.
+        let event = match action {
+            RouletteAction::PlaceBet { bet, amount } => RouletteEvent::BetPlaced {
+                player_id,
+                bet,
+                amount,
+            },
+            RouletteAction::ClearBets => RouletteEvent::BetsCleared { player_id },
+            RouletteAction::Spin => RouletteEvent::SpinRequested { player_id },
+        };
+        Ok(GameEvent::Roulette(event))
+    }
+
+    fn broadcast(&self, update: &serde_json::Value) {
+        for player_id in &self.players {
+            self.outbox.send_to_player(&self.id, player_id, update);
+        }
+    }
+
+    fn broadcast_except(&self, player_id: &str, update: &serde_json::Value) {
+        for other in self.players.iter().filter(|other| *other != player_id) {
+            self.outbox.send_to_player(&self.id, other, update);
+        }
+    }
+
+    fn round_in_progress(&self) -> bool {
+        // Rounds are not played yet, so there is never one to wait for.
+        false
     }
 
-    async fn broadcast(&self, message: &str) {
-        // for player in &self.players {
-        //     // send message to player
-        // }
+    fn void_round(&mut self) {
+        self.broadcast(&json!({ "event": "round_voided", "room_id": self.id }));
     }
 }
 
 impl RouletteRoom {
-    pub fn new(id: String, game_type: GameType) -> RouletteRoom {
+    pub fn new(
+        id: String,
+        game_type: GameType,
+        settings: RoomSettings,
+        outbox: Arc<dyn Outbox>,
+    ) -> RouletteRoom {
         RouletteRoom {
             id,
             players: HashSet::new(),
             game_type,
+            settings,
+            outbox,
         }
     }
 }
@@ -1,3 +1,6 @@
+mod action;
 mod game;
 
+pub use action::{RouletteAction, RouletteEvent};
+
 pub use game::RouletteRoom;
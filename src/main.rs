@@ -5,11 +5,13 @@ mod auth;
 mod config;
 mod db;
 mod game;
+mod notifier;
 mod queues;
 
 mod message;
 mod server;
 
+use std::path::Path;
 use std::sync::Arc;
 use tokio;
 
@@ -17,14 +19,34 @@ use crate::queues::db_queue;
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 3)]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Protocol definitions for the clients, generated from the message types
+    match std::env::args().nth(1).as_deref() {
+        Some("generate-protocol") => {
+            message::codegen::generate(Path::new(message::codegen::PROTOCOL_DIR))?;
+            return Ok(());
+        }
+        Some("check-protocol") => {
+            let stale = message::codegen::stale_files(Path::new(message::codegen::PROTOCOL_DIR))?;
+            if !stale.is_empty() {
+                return Err(format!("Protocol definitions are out of date: {:?}", stale).into());
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+
     let config = config::Config::from_file("config/settings.ini")?;
     let db_pool = Arc::new(db::DbPool::new()?);
 
     let db_queue = Arc::new(db_queue::create_db_queue(db_pool.clone(), None).await?);
 
-    let game_manager = game::GameManager::new(db_pool.clone(), db_queue.clone());
+    let notifier = notifier::from_config(&config)?;
+    let auth = auth::AuthService::new(db_pool.clone(), db_queue.clone(), notifier, &config)?;
+
+    server::start_server(config, db_pool, db_queue.clone(), auth).await?;
 
-    server::start_server(config, game_manager).await?;
+    // Connections and rooms are gone by now, so nothing publishes anymore
+    db_queue.shutdown().await?;
 
     Ok(())
 }
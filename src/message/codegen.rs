@@ -0,0 +1,157 @@
+use schemars::{schema_for, JsonSchema};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use ts_rs::TS;
+use uuid::Uuid;
+
+use super::{ClientEnvelope, ServerEnvelope};
+use crate::game::{GameEvent, GameType, PokerAction, RouletteAction};
+
+/// Directory the protocol definitions are generated into, relative to the repository root.
+pub const PROTOCOL_DIR: &str = "protocol";
+const SCHEMA_DIR: &str = "schema";
+const TYPESCRIPT_DIR: &str = "ts";
+
+#[derive(Error, Debug)]
+pub enum CodegenError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("TypeScript export error: {0}")]
+    Export(#[from] ts_rs::ExportError),
+}
+
+/// Writes the JSON Schema and TypeScript definitions of the protocol to `dir`, replacing
+/// the previous ones.
+pub fn generate(dir: &Path) -> Result<(), CodegenError> {
+    let files = render()?;
+
+    for subdir in [SCHEMA_DIR, TYPESCRIPT_DIR] {
+        match fs::remove_dir_all(dir.join(subdir)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    for (path, contents) in files {
+        let path = dir.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, contents)?;
+    }
+    Ok(())
+}
+
+/// Files in `dir` that differ from what `generate` would write: missing, changed or no
+/// longer generated.
+pub fn stale_files(dir: &Path) -> Result<Vec<PathBuf>, CodegenError> {
+    let files = render()?;
+
+    let mut stale: Vec<PathBuf> = files
+        .iter()
+        .filter(|(path, contents)| {
+            fs::read_to_string(dir.join(path)).ok().as_ref() != Some(contents)
+        })
+        .map(|(path, _)| path.clone())
+        .collect();
+    for subdir in [SCHEMA_DIR, TYPESCRIPT_DIR] {
+        let mut existing = Vec::new();
+        match read_tree(dir, Path::new(subdir), &mut existing) {
+            Err(CodegenError::Io(e)) if e.kind() == ErrorKind::NotFound => continue,
+            result => result?,
+        }
+        stale.extend(
+            existing
+                .into_iter()
+                .map(|(path, _)| path)
+                .filter(|path| !files.contains_key(path)),
+        );
+    }
+    Ok(stale)
+}
+
+/// Renders every generated file, keyed by its path below the protocol directory.
+fn render() -> Result<BTreeMap<PathBuf, String>, CodegenError> {
+    let mut files = BTreeMap::new();
+
+    add_schema::<ClientEnvelope>(&mut files, "ClientEnvelope")?;
+    add_schema::<ServerEnvelope<'static>>(&mut files, "ServerEnvelope")?;
+    add_schema::<GameType>(&mut files, "GameType")?;
+    add_schema::<RouletteAction>(&mut files, "RouletteAction")?;
+    add_schema::<PokerAction>(&mut files, "PokerAction")?;
+    add_schema::<GameEvent>(&mut files, "GameEvent")?;
+
+    // ts-rs can only export into a directory, so the TypeScript takes a detour through one
+    let scratch = std::env::temp_dir().join(format!("rorust-protocol-{}", Uuid::new_v4()));
+    let exported = export_typescript(&scratch);
+    let _ = fs::remove_dir_all(&scratch);
+    for (name, contents) in exported? {
+        files.insert(Path::new(TYPESCRIPT_DIR).join(name), contents);
+    }
+
+    Ok(files)
+}
+
+fn add_schema<T: JsonSchema>(
+    files: &mut BTreeMap<PathBuf, String>,
+    name: &str,
+) -> Result<(), CodegenError> {
+    let schema = serde_json::to_string_pretty(&schema_for!(T))?;
+    files.insert(
+        Path::new(SCHEMA_DIR).join(format!("{}.schema.json", name)),
+        schema + "\n",
+    );
+    Ok(())
+}
+
+/// Exports the protocol types and everything they refer to, one file per type.
+fn export_typescript(dir: &Path) -> Result<Vec<(PathBuf, String)>, CodegenError> {
+    ClientEnvelope::export_all_to(dir)?;
+    ServerEnvelope::export_all_to(dir)?;
+    GameType::export_all_to(dir)?;
+    RouletteAction::export_all_to(dir)?;
+    PokerAction::export_all_to(dir)?;
+    GameEvent::export_all_to(dir)?;
+
+    let mut files = Vec::new();
+    read_tree(dir, Path::new(""), &mut files)?;
+    Ok(files)
+}
+
+/// Reads every file below `root.join(relative)`, keyed by its path relative to `root`.
+fn read_tree(
+    root: &Path,
+    relative: &Path,
+    files: &mut Vec<(PathBuf, String)>,
+) -> Result<(), CodegenError> {
+    for entry in fs::read_dir(root.join(relative))? {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            read_tree(root, &path, files)?;
+        } else {
+            files.push((path, fs::read_to_string(entry.path())?));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_protocol_is_up_to_date() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(PROTOCOL_DIR);
+        let stale = stale_files(&dir).unwrap();
+        assert!(
+            stale.is_empty(),
+            "Protocol definitions are out of date, run `cargo run -- generate-protocol`: {:?}",
+            stale
+        );
+    }
+}
@@ -1,37 +1,689 @@
+pub mod codegen;
+
+use flate2::write::DeflateEncoder;
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::io::Write;
+use thiserror::Error;
+use tokio_tungstenite::tungstenite::protocol::Message;
+use ts_rs::TS;
+
+use crate::auth::policy::Permission;
+use crate::auth::Role;
+use crate::server::registry::SessionInfo;
+use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Version of the message protocol the server speaks. Bumped whenever `ClientMessage` or
+/// `ServerMessage` change in a way that older clients cannot handle.
+pub const PROTOCOL_VERSION: u32 = 1;
+/// Oldest protocol version clients may still speak.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// A client message with an optional id of the client's choosing, e.g.
+/// `{"request_id": "42", "type": "GetBalance"}`. The server tags its direct reply with the
+/// same id so that clients with several requests in flight can match them up.
+#[derive(Debug, Deserialize, JsonSchema, TS)]
+pub struct ClientEnvelope {
+    #[serde(default)]
+    #[ts(optional)]
+    pub request_id: Option<String>,
+    #[serde(flatten)]
+    pub message: ClientMessage,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, TS)]
 #[serde(tag = "type", content = "data")]
 pub enum ClientMessage {
+    /// Has to be the first message on a socket. `capabilities` lists the optional features
+    /// the client knows how to use.
+    Hello {
+        protocol_version: u32,
+        #[serde(default)]
+        #[ts(optional)]
+        client_name: Option<String>,
+        #[serde(default)]
+        #[ts(as = "Option<Vec<String>>", optional)]
+        capabilities: Vec<String>,
+    },
     Auth {
         username: String,
         password: String,
     },
+    Register {
+        username: String,
+        email: String,
+        password: String,
+        first_name: String,
+        last_name: String,
+        citizen_id: String,
+    },
+    GuestLogin,
+    UpgradeGuest {
+        username: String,
+        email: String,
+        password: String,
+        first_name: String,
+        last_name: String,
+        citizen_id: String,
+    },
+    GetBalance,
+    RefreshToken {
+        refresh_token: String,
+    },
+    Logout {
+        #[serde(default)]
+        #[ts(optional)]
+        refresh_token: Option<String>,
+        #[serde(default)]
+        #[ts(as = "Option<bool>", optional)]
+        all_devices: bool,
+    },
+    Resume {
+        token: String,
+        #[ts(type = "number")]
+        last_seq: u64,
+    },
+    ChangePassword {
+        current_password: String,
+        new_password: String,
+    },
+    RequestPasswordReset {
+        email: String,
+    },
+    ResetPassword {
+        token: String,
+        new_password: String,
+    },
+    ListSessions,
+    EndSession {
+        session_id: Uuid,
+    },
     SelectGame {
         game_type: String,
+        #[serde(default)]
+        #[ts(as = "Option<bool>", optional)]
+        high_limit: bool,
+        #[serde(default)]
+        #[ts(as = "Option<bool>", optional)]
+        free_play: bool,
+    },
+    CreatePrivateRoom {
+        game_type: String,
     },
+    /// An action at the player's table, e.g. `{"action": "raise", "params": {"amount": 20}}`.
+    /// The server decodes it as an action of the room's game (`RouletteAction`, `PokerAction`)
+    /// before the room sees it. Actions without params may leave them out.
     GameAction {
         action: String,
+        #[serde(default)]
+        #[ts(as = "Option<serde_json::Value>", optional)]
         params: serde_json::Value,
     },
+    Admin {
+        command: AdminCommand,
+    },
     Quit,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl ClientMessage {
+    /// snake_case name of the message type, used to look up its rate limit.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ClientMessage::Hello { .. } => "hello",
+            ClientMessage::Auth { .. } => "auth",
+            ClientMessage::Register { .. } => "register",
+            ClientMessage::GuestLogin => "guest_login",
+            ClientMessage::UpgradeGuest { .. } => "upgrade_guest",
+            ClientMessage::GetBalance => "get_balance",
+            ClientMessage::RefreshToken { .. } => "refresh_token",
+            ClientMessage::Logout { .. } => "logout",
+            ClientMessage::Resume { .. } => "resume",
+            ClientMessage::ChangePassword { .. } => "change_password",
+            ClientMessage::RequestPasswordReset { .. } => "request_password_reset",
+            ClientMessage::ResetPassword { .. } => "reset_password",
+            ClientMessage::ListSessions => "list_sessions",
+            ClientMessage::EndSession { .. } => "end_session",
+            ClientMessage::SelectGame { .. } => "select_game",
+            ClientMessage::CreatePrivateRoom { .. } => "create_private_room",
+            ClientMessage::GameAction { .. } => "game_action",
+            ClientMessage::Admin { .. } => "admin",
+            ClientMessage::Quit => "quit",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(tag = "type", content = "data")]
+pub enum AdminCommand {
+    ListRoles { account_id: i32 },
+    GrantRole { account_id: i32, role: Role },
+    RevokeRole { account_id: i32, role: Role },
+    BanAccount { account_id: i32 },
+    ListLockouts,
+    UnlockAccount { account_id: i32 },
+    ListSessions { account_id: i32 },
+    EndSession { session_id: Uuid },
+    ConnectionStats,
+}
+
+/// What went wrong, for clients to react to in code. The `message` of an `Error` is English
+/// text meant for logs; clients word errors themselves, using the error's `details`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The message could not be decoded.
+    InvalidMessage,
+    /// The message is valid but not expected at this point.
+    UnexpectedMessage,
+    /// The message needs a logged in session.
+    Unauthorized,
+    UnknownSession,
+    InvalidGameType,
+    RoomNotFound,
+    NotInRoom,
+    /// The game has no such action; `details` lists the `expected` ones.
+    InvalidAction,
+    /// The action's params are malformed or out of range; `details` says why.
+    InvalidParams,
+    NotYourTurn,
+    InsufficientFunds,
+    BalanceUnavailable,
+    /// Too many messages; `details` has the `message_type` and `retry_after_ms`.
+    RateLimited,
+    AdminCommandFailed,
+}
+
+/// A server message as it goes out. Direct replies carry the `request_id` of the client
+/// message they answer, if it had one. Messages the server sends on its own, like game
+/// updates or the shutdown notice, carry `seq` instead: a number that grows by one with every
+/// such message on the connection, so clients can tell if one went missing. It is not the
+/// `seq` of a `GameUpdate`, which counts the updates of a room session across reconnects.
+#[derive(Debug, Serialize, JsonSchema, TS)]
+pub struct ServerEnvelope<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub request_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional, type = "number")]
+    pub seq: Option<u64>,
+    #[serde(flatten)]
+    pub message: &'a ServerMessage,
+}
+
+impl<'a> ServerEnvelope<'a> {
+    pub fn reply(message: &'a ServerMessage, request_id: Option<&'a str>) -> Self {
+        ServerEnvelope {
+            request_id,
+            seq: None,
+            message,
+        }
+    }
+
+    pub fn push(message: &'a ServerMessage, seq: u64) -> Self {
+        ServerEnvelope {
+            request_id: None,
+            seq: Some(seq),
+            message,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, TS)]
 #[serde(tag = "type", content = "data")]
 pub enum ServerMessage {
-    AuthSuccess { token: String },
+    /// Answer to `Hello`. `features` lists the optional features the server supports.
+    Welcome {
+        protocol_version: u32,
+        server_version: String,
+        game_types: Vec<String>,
+        features: Vec<String>,
+    },
+    /// The client did not start with `Hello` or speaks a protocol version outside
+    /// `min_version..=max_version`. The socket is closed right after.
+    ProtocolRejected {
+        reason: String,
+        min_version: u32,
+        max_version: u32,
+    },
+    AuthSuccess {
+        token: String,
+        refresh_token: String,
+        #[ts(type = "number")]
+        expires_in: i64,
+    },
     AuthFailed,
-    GameAssigned { room_id: String, game_type: String },
-    GameUpdate { state: serde_json::Value },
-    Error { message: String },
-    Echo { message: serde_json::Value },
+    AccountLocked {
+        #[ts(type = "number")]
+        locked_until: i64,
+    },
+    LoginThrottled {
+        #[ts(type = "number")]
+        retry_after_secs: u64,
+    },
+    RegisterSuccess {
+        account_id: i32,
+    },
+    RegisterFailed {
+        reason: String,
+        /// The registration field that was turned down, e.g. `email`.
+        field: Option<String>,
+        /// Whether `field` is already registered to another account, as opposed to invalid.
+        taken: bool,
+    },
+    GuestSession {
+        token: String,
+        player_id: String,
+        display_name: String,
+        #[ts(type = "number")]
+        expires_in: i64,
+        #[ts(type = "string")]
+        balance: Decimal,
+    },
+    GuestUpgraded {
+        account_id: i32,
+        token: String,
+        refresh_token: String,
+        #[ts(type = "number")]
+        expires_in: i64,
+    },
+    Balance {
+        #[ts(type = "string")]
+        balance: Decimal,
+        play_money: bool,
+    },
+    LoggedOut,
+    PasswordChanged,
+    PasswordResetRequested,
+    PasswordChangeFailed {
+        reason: String,
+    },
+    Sessions {
+        current_session_id: Uuid,
+        sessions: Vec<SessionInfo>,
+    },
+    SessionClosed {
+        session_id: Uuid,
+    },
+    SessionEnded {
+        reason: String,
+    },
+    SessionLimitReached {
+        max_sessions: usize,
+    },
+    /// The server stopped accepting connections. Running rounds get up to `timeout_secs`
+    /// to finish before the socket is closed.
+    ServerShutdown {
+        #[ts(type = "number")]
+        timeout_secs: u64,
+    },
+    GameAssigned {
+        room_id: String,
+        game_type: String,
+    },
+    Resumed {
+        room_id: String,
+        #[ts(type = "number")]
+        last_seq: u64,
+        history_truncated: bool,
+    },
+    ResumeFailed {
+        reason: String,
+    },
+    GameUpdate {
+        #[ts(type = "number")]
+        seq: u64,
+        state: serde_json::Value,
+    },
+    PermissionDenied {
+        permission: Permission,
+        reason: String,
+    },
+    AdminResult {
+        result: serde_json::Value,
+    },
+    Error {
+        code: ErrorCode,
+        message: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        #[ts(optional)]
+        details: Option<serde_json::Value>,
+    },
+    Echo {
+        message: serde_json::Value,
+    },
+}
+
+impl ServerMessage {
+    /// An `Error` without details.
+    pub fn error(code: ErrorCode, message: impl Into<String>) -> Self {
+        ServerMessage::Error {
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
 }
 
-pub fn parse_client_message(msg: &str) -> Result<ClientMessage, serde_json::Error> {
+#[derive(Error, Debug)]
+pub enum MessageError {
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("MessagePack encode error: {0}")]
+    Encode(#[from] rmp_serde::encode::Error),
+    #[error("MessagePack decode error: {0}")]
+    Decode(#[from] rmp_serde::decode::Error),
+    #[error("Compression error: {0}")]
+    Compress(#[from] std::io::Error),
+}
+
+/// Serialization of a connection's messages.
+///
+/// JSON travels in text frames and MessagePack in binary frames. Both carry the same
+/// messages with the same field names, so clients can switch by changing the subprotocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Json,
+    MessagePack,
+}
+
+/// Compression of server messages, negotiated by appending `.deflate` to the subprotocol.
+///
+/// tungstenite does not implement the permessage-deflate extension, so messages are
+/// compressed before they are framed instead. Only messages of at least `threshold` bytes are
+/// compressed. JSON messages below it stay text frames, so they read like on `rorust.json`.
+/// Every binary server frame of such a connection starts with a byte that tells how the rest
+/// is to be read: `0` is the encoded message as is, `1` is the encoded message compressed with
+/// raw DEFLATE. Client messages are never compressed. The framing is documented for client
+/// authors in `protocol/README.md`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression {
+    pub threshold: usize,
+}
+
+impl Compression {
+    pub const PROTOCOL_SUFFIX: &'static str = ".deflate";
+    const UNCOMPRESSED: u8 = 0;
+    const DEFLATE: u8 = 1;
+
+    fn compress(self, payload: Vec<u8>) -> Result<Vec<u8>, std::io::Error> {
+        if payload.len() < self.threshold {
+            let mut frame = Vec::with_capacity(payload.len() + 1);
+            frame.push(Self::UNCOMPRESSED);
+            frame.extend_from_slice(&payload);
+            return Ok(frame);
+        }
+
+        let mut encoder = DeflateEncoder::new(vec![Self::DEFLATE], flate2::Compression::fast());
+        encoder.write_all(&payload)?;
+        encoder.finish()
+    }
+}
+
+/// Wire format of a connection, negotiated through `Sec-WebSocket-Protocol`: `rorust.json`
+/// or `rorust.msgpack`, each optionally followed by `.deflate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Encoding {
+    pub format: Format,
+    pub compression: Option<Compression>,
+}
+
+impl Encoding {
+    pub const JSON_PROTOCOL: &'static str = "rorust.json";
+    pub const MESSAGE_PACK_PROTOCOL: &'static str = "rorust.msgpack";
+
+    /// Reads the encoding of a subprotocol. Compressed subprotocols are only recognised if
+    /// the server has compression enabled.
+    pub fn from_protocol(protocol: &str, compression: Option<Compression>) -> Option<Encoding> {
+        let (protocol, compression) = match protocol.strip_suffix(Compression::PROTOCOL_SUFFIX) {
+            Some(protocol) => (protocol, Some(compression?)),
+            None => (protocol, None),
+        };
+        let format = match protocol {
+            Self::JSON_PROTOCOL => Format::Json,
+            Self::MESSAGE_PACK_PROTOCOL => Format::MessagePack,
+            _ => return None,
+        };
+        Some(Encoding {
+            format,
+            compression,
+        })
+    }
+}
+
+pub fn parse_client_message(msg: &str) -> Result<ClientEnvelope, serde_json::Error> {
     serde_json::from_str(msg)
 }
 
-pub fn serialize_server_message(msg: &ServerMessage) -> Result<String, serde_json::Error> {
+pub fn serialize_server_message(msg: &ServerEnvelope) -> Result<String, serde_json::Error> {
     serde_json::to_string(msg)
 }
+
+/// Decodes a MessagePack client message. Structs are expected as maps and ids as strings,
+/// like in JSON.
+pub fn parse_client_message_msgpack(data: &[u8]) -> Result<ClientEnvelope, MessageError> {
+    let mut deserializer = rmp_serde::Deserializer::new(data).with_human_readable();
+    Ok(ClientEnvelope::deserialize(&mut deserializer)?)
+}
+
+/// Reads just the `request_id` of a client frame that failed to parse as a whole, so that the
+/// error can still be matched to the request.
+pub fn peek_request_id(frame: &Message) -> Option<String> {
+    #[derive(Deserialize)]
+    struct RequestId {
+        request_id: Option<String>,
+    }
+
+    let peeked = match frame {
+        Message::Text(text) => serde_json::from_str::<RequestId>(text).ok(),
+        Message::Binary(data) => {
+            let mut deserializer = rmp_serde::Deserializer::new(&data[..]).with_human_readable();
+            RequestId::deserialize(&mut deserializer).ok()
+        }
+        _ => None,
+    };
+    peeked?.request_id
+}
+
+/// Encodes a server message into the frame type of the connection's encoding, compressing
+/// it if the connection asked for that.
+pub fn encode_server_message(
+    msg: &ServerEnvelope,
+    encoding: Encoding,
+) -> Result<Message, MessageError> {
+    let frame = match encoding.format {
+        Format::Json => Message::Text(serialize_server_message(msg)?),
+        Format::MessagePack => {
+            let mut data = Vec::new();
+            let mut serializer = rmp_serde::Serializer::new(&mut data)
+                .with_struct_map()
+                .with_human_readable();
+            msg.serialize(&mut serializer)?;
+            Message::Binary(data)
+        }
+    };
+
+    match (encoding.compression, frame) {
+        (Some(compression), Message::Text(text)) if text.len() < compression.threshold => {
+            Ok(Message::Text(text))
+        }
+        (Some(compression), frame) => Ok(Message::Binary(compression.compress(frame.into_data())?)),
+        (None, frame) => Ok(frame),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::DeflateDecoder;
+    use serde_json::json;
+    use std::io::Read;
+
+    const MESSAGE_PACK: Encoding = Encoding {
+        format: Format::MessagePack,
+        compression: None,
+    };
+
+    #[test]
+    fn client_envelopes_carry_an_optional_request_id() {
+        let tagged = parse_client_message(r#"{"request_id":"42","type":"GetBalance"}"#).unwrap();
+        assert_eq!(tagged.request_id.as_deref(), Some("42"));
+        assert!(matches!(tagged.message, ClientMessage::GetBalance));
+
+        let untagged = parse_client_message(r#"{"type":"GetBalance"}"#).unwrap();
+        assert_eq!(untagged.request_id, None);
+    }
+
+    #[test]
+    fn replies_echo_the_request_id_and_pushes_carry_a_seq() {
+        let message = ServerMessage::LoggedOut;
+
+        let reply = serialize_server_message(&ServerEnvelope::reply(&message, Some("42"))).unwrap();
+        assert_eq!(reply, r#"{"request_id":"42","type":"LoggedOut"}"#);
+        let push = serialize_server_message(&ServerEnvelope::push(&message, 3)).unwrap();
+        assert_eq!(push, r#"{"seq":3,"type":"LoggedOut"}"#);
+    }
+
+    #[test]
+    fn peeks_the_request_id_of_a_message_that_does_not_parse() {
+        let text = Message::Text(r#"{"request_id":"42","type":"NoSuchMessage"}"#.to_string());
+        assert!(parse_client_message(text.to_text().unwrap()).is_err());
+        assert_eq!(peek_request_id(&text).as_deref(), Some("42"));
+
+        let value = json!({ "request_id": "43", "type": "Auth", "data": {} });
+        let binary = Message::Binary(rmp_serde::to_vec_named(&value).unwrap());
+        assert_eq!(peek_request_id(&binary).as_deref(), Some("43"));
+
+        assert_eq!(
+            peek_request_id(&Message::Text("not json".to_string())),
+            None
+        );
+    }
+
+    #[test]
+    fn message_pack_client_messages_decode_like_json() {
+        let value = json!({
+            "request_id": "7",
+            "type": "Auth",
+            "data": { "username": "alice", "password": "hunter22" },
+        });
+        let data = rmp_serde::to_vec_named(&value).unwrap();
+
+        let envelope = parse_client_message_msgpack(&data).unwrap();
+        assert_eq!(envelope.request_id.as_deref(), Some("7"));
+        assert!(matches!(
+            envelope.message,
+            ClientMessage::Auth { username, .. } if username == "alice"
+        ));
+    }
+
+    #[test]
+    fn server_messages_use_the_frame_type_of_the_format() {
+        let envelope = ServerEnvelope::reply(&ServerMessage::LoggedOut, None);
+
+        match encode_server_message(&envelope, Encoding::default()).unwrap() {
+            Message::Text(text) => assert_eq!(text, r#"{"type":"LoggedOut"}"#),
+            other => panic!("expected a text frame, got {:?}", other),
+        }
+        match encode_server_message(&envelope, MESSAGE_PACK).unwrap() {
+            Message::Binary(data) => {
+                let mut deserializer =
+                    rmp_serde::Deserializer::new(&data[..]).with_human_readable();
+                let decoded = serde_json::Value::deserialize(&mut deserializer).unwrap();
+                assert_eq!(decoded, json!({ "type": "LoggedOut" }));
+            }
+            other => panic!("expected a binary frame, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reads_the_format_of_a_subprotocol() {
+        assert_eq!(
+            Encoding::from_protocol("rorust.msgpack", None),
+            Some(MESSAGE_PACK)
+        );
+        assert_eq!(
+            Encoding::from_protocol("rorust.json", None),
+            Some(Encoding::default())
+        );
+        assert_eq!(Encoding::from_protocol("rorust.xml", None), None);
+    }
+
+    fn encode(message: &ServerMessage, encoding: Encoding) -> Vec<u8> {
+        let envelope = ServerEnvelope::reply(message, Some("7"));
+        match encode_server_message(&envelope, encoding).unwrap() {
+            Message::Binary(data) => data,
+            other => panic!("expected a binary frame, got {:?}", other),
+        }
+    }
+
+    fn decompress(frame: &[u8]) -> Vec<u8> {
+        match frame.split_first() {
+            Some((&Compression::UNCOMPRESSED, payload)) => payload.to_vec(),
+            Some((&Compression::DEFLATE, payload)) => {
+                let mut decompressed = Vec::new();
+                DeflateDecoder::new(payload)
+                    .read_to_end(&mut decompressed)
+                    .unwrap();
+                decompressed
+            }
+            _ => panic!("unknown compression flag"),
+        }
+    }
+
+    #[test]
+    fn compressed_messages_round_trip() {
+        let encoding =
+            Encoding::from_protocol("rorust.json.deflate", Some(Compression { threshold: 64 }))
+                .unwrap();
+        let message = ServerMessage::error(ErrorCode::InvalidMessage, "a".repeat(1000));
+        let frame = encode(&message, encoding);
+
+        assert_eq!(frame[0], Compression::DEFLATE);
+        assert!(frame.len() < 1000);
+        let expected =
+            serialize_server_message(&ServerEnvelope::reply(&message, Some("7"))).unwrap();
+        assert_eq!(decompress(&frame), expected.into_bytes());
+    }
+
+    #[test]
+    fn small_messages_are_sent_uncompressed() {
+        let encoding = Encoding::from_protocol(
+            "rorust.msgpack.deflate",
+            Some(Compression { threshold: 1024 }),
+        )
+        .unwrap();
+        let frame = encode(&ServerMessage::LoggedOut, encoding);
+
+        assert_eq!(frame[0], Compression::UNCOMPRESSED);
+        let mut deserializer = rmp_serde::Deserializer::new(&frame[1..]).with_human_readable();
+        let decoded = serde_json::Value::deserialize(&mut deserializer).unwrap();
+        assert_eq!(decoded, json!({ "request_id": "7", "type": "LoggedOut" }));
+    }
+
+    #[test]
+    fn small_json_messages_stay_text_frames() {
+        let encoding =
+            Encoding::from_protocol("rorust.json.deflate", Some(Compression { threshold: 1024 }))
+                .unwrap();
+        let envelope = ServerEnvelope::reply(&ServerMessage::LoggedOut, None);
+
+        match encode_server_message(&envelope, encoding).unwrap() {
+            Message::Text(text) => assert_eq!(text, r#"{"type":"LoggedOut"}"#),
+            other => panic!("expected a text frame, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn compressed_subprotocols_need_compression_enabled() {
+        assert_eq!(Encoding::from_protocol("rorust.json.deflate", None), None);
+        assert_eq!(
+            Encoding::from_protocol("rorust.msgpack.deflate", Some(Compression { threshold: 1 })),
+            Some(Encoding {
+                format: Format::MessagePack,
+                compression: Some(Compression { threshold: 1 }),
+            })
+        );
+    }
+}
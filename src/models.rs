@@ -89,6 +89,8 @@ pub struct Account {
     pub created_at: Option<NaiveDateTime>,
     pub updated_at: Option<NaiveDateTime>,
     pub deleted_at: Option<NaiveDateTime>,
+    pub failed_login_attempts: i32,
+    pub locked_until: Option<NaiveDateTime>,
 }
 
 #[derive(Insertable)]
@@ -101,7 +103,7 @@ pub struct NewAccount {
     pub is_active: Option<bool>,
 }
 
-#[derive(AsChangeset)]
+#[derive(AsChangeset, Default)]
 #[diesel(table_name = crate::schema::accounts)]
 pub struct UpdateAccount {
     pub email: Option<String>,
@@ -109,6 +111,30 @@ pub struct UpdateAccount {
     pub password: Option<String>,
     pub last_login: Option<NaiveDateTime>,
     pub is_active: Option<bool>,
+    pub failed_login_attempts: Option<i32>,
+    pub locked_until: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Queryable, Identifiable)]
+#[diesel(table_name = crate::schema::password_reset_tokens)]
+pub struct PasswordResetToken {
+    pub id: i32,
+    pub account_id: i32,
+    pub selector: String,
+    pub verifier_hash: String,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::password_reset_tokens)]
+pub struct NewPasswordResetToken {
+    pub account_id: i32,
+    pub selector: String,
+    pub verifier_hash: String,
+    pub expires_at: NaiveDateTime,
 }
 
 #[derive(Queryable, Insertable, Serialize, Deserialize)]
@@ -172,6 +198,26 @@ pub struct UpdatePlayer {
     pub room_id: Option<i32>,
 }
 
+#[derive(Queryable, Identifiable)]
+#[diesel(table_name = crate::schema::revoked_tokens)]
+pub struct RevokedToken {
+    pub id: i32,
+    pub account_id: i32,
+    pub jti: Option<String>,
+    pub reason: Option<String>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub revoked_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::revoked_tokens)]
+pub struct NewRevokedToken {
+    pub account_id: i32,
+    pub jti: Option<String>,
+    pub reason: Option<String>,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
 #[derive(Queryable, Identifiable, AsChangeset)]
 #[diesel(table_name = crate::schema::roles)]
 pub struct Role {
@@ -0,0 +1,90 @@
+use async_trait::async_trait;
+use std::path::PathBuf;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use tracing::info;
+
+use crate::config::Config;
+
+#[derive(Error, Debug)]
+pub enum NotifierError {
+    #[error("Unknown notifier sink: {0}")]
+    UnknownSink(String),
+    #[error("The file notifier needs a path")]
+    MissingPath,
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// A message for a player, e.g. the link of a password reset.
+pub struct Notification {
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers notifications to players. The sinks in this module are meant for development;
+/// production deployments plug in a sink that talks to a mail or SMS provider.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, notification: Notification) -> Result<(), NotifierError>;
+}
+
+/// Writes notifications to the application log.
+pub struct LogNotifier;
+
+#[async_trait]
+impl Notifier for LogNotifier {
+    async fn notify(&self, notification: Notification) -> Result<(), NotifierError> {
+        info!(
+            "Notification to {}: {}\n{}",
+            notification.recipient, notification.subject, notification.body
+        );
+        Ok(())
+    }
+}
+
+/// Appends notifications to a file.
+pub struct FileNotifier {
+    path: PathBuf,
+}
+
+impl FileNotifier {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileNotifier { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl Notifier for FileNotifier {
+    async fn notify(&self, notification: Notification) -> Result<(), NotifierError> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        let entry = format!(
+            "To: {}\nSubject: {}\n\n{}\n\n",
+            notification.recipient, notification.subject, notification.body
+        );
+        file.write_all(entry.as_bytes()).await?;
+        Ok(())
+    }
+}
+
+/// Builds the sink selected by the `[notifier]` section of the config.
+pub fn from_config(config: &Config) -> Result<Arc<dyn Notifier>, NotifierError> {
+    match config.notifier_sink.as_str() {
+        "log" => Ok(Arc::new(LogNotifier)),
+        "file" => {
+            let path = config
+                .notifier_path
+                .as_ref()
+                .ok_or(NotifierError::MissingPath)?;
+            Ok(Arc::new(FileNotifier::new(path)))
+        }
+        other => Err(NotifierError::UnknownSink(other.to_string())),
+    }
+}
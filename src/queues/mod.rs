@@ -10,7 +10,7 @@ use serde::{de::DeserializeOwned, Serialize};
 use std::{env, sync::Arc};
 use thiserror::Error;
 use tokio::sync::Mutex;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 // Generic error type for queue operations
 #[derive(Debug, Error)]
@@ -164,6 +164,11 @@ impl<P: OperationProcessor> Queue<P> {
             .basic_qos(config.prefetch_count, BasicQosOptions::default())
             .await?;
 
+        // Publisher confirms, so that shutdown can wait for the broker to take every message
+        channel
+            .confirm_select(ConfirmSelectOptions::default())
+            .await?;
+
         // Set up exchanges and queues
         Self::setup_infrastructure(&channel, &config).await?;
 
@@ -350,10 +355,30 @@ impl<P: OperationProcessor> Queue<P> {
         Ok(())
     }
 
+    pub fn is_connected(&self) -> bool {
+        self.connection.status().connected()
+    }
+
+    /// Stops consuming, waits until the broker has confirmed every message published so far
+    /// and closes the channel and connection.
     pub async fn shutdown(&self) -> Result<(), QueueError> {
+        self.channel
+            .basic_cancel(&self.config.consumer_tag, BasicCancelOptions::default())
+            .await?;
+        // The consumer stream ends once cancelled; let the delivery in progress be acked
         if let Some(handle) = self.consumer_handle.lock().await.take() {
-            handle.abort();
+            let _ = handle.await;
         }
+
+        let returned = self.channel.wait_for_confirms().await?;
+        if !returned.is_empty() {
+            warn!(
+                "{} {} messages were returned by the broker",
+                returned.len(),
+                self.processor.operation_type()
+            );
+        }
+
         self.channel.close(0, "Shutting down").await?;
         self.connection.close(0, "Shutting down").await?;
         Ok(())
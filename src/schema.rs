@@ -32,6 +32,22 @@ diesel::table! {
         created_at -> Nullable<Timestamptz>,
         updated_at -> Nullable<Timestamptz>,
         deleted_at -> Nullable<Timestamptz>,
+        failed_login_attempts -> Int4,
+        locked_until -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    password_reset_tokens (id) {
+        id -> Int4,
+        account_id -> Int4,
+        #[max_length = 32]
+        selector -> Varchar,
+        #[max_length = 255]
+        verifier_hash -> Varchar,
+        expires_at -> Timestamptz,
+        used_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
     }
 }
 
@@ -67,6 +83,19 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    revoked_tokens (id) {
+        id -> Int4,
+        account_id -> Int4,
+        #[max_length = 64]
+        jti -> Nullable<Varchar>,
+        #[max_length = 100]
+        reason -> Nullable<Varchar>,
+        expires_at -> Nullable<Timestamptz>,
+        revoked_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     roles (id) {
         id -> Int4,
@@ -119,16 +148,20 @@ diesel::table! {
 diesel::joinable!(account_roles -> accounts (account_id));
 diesel::joinable!(account_roles -> roles (role_id));
 diesel::joinable!(accounts -> users (user_id));
+diesel::joinable!(password_reset_tokens -> accounts (account_id));
 diesel::joinable!(player_bets -> players (player_id));
 diesel::joinable!(player_bets -> rooms (room_id));
 diesel::joinable!(players -> accounts (account_id));
 diesel::joinable!(players -> rooms (room_id));
+diesel::joinable!(revoked_tokens -> accounts (account_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     account_roles,
     accounts,
+    password_reset_tokens,
     player_bets,
     players,
+    revoked_tokens,
     roles,
     rooms,
     users,
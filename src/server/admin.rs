@@ -0,0 +1,76 @@
+use serde_json::json;
+
+use crate::message::{AdminCommand, ErrorCode, ServerMessage};
+use crate::server::{end_session, ConnectionContext};
+
+/// Addresses listed by `ConnectionStats`, busiest first.
+const CONNECTION_STATS_TOP_ADDRESSES: usize = 20;
+
+/// Runs an admin command. Callers must have checked the command's required permission.
+pub(super) async fn handle_admin_command(
+    ctx: &mut ConnectionContext,
+    command: AdminCommand,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let admin = ctx
+        .claims
+        .as_ref()
+        .map(|claims| claims.username.clone())
+        .unwrap_or_default();
+    println!("Admin command from {}: {:?}", admin, command);
+
+    let result = match command {
+        AdminCommand::ListRoles { account_id } => ctx
+            .auth
+            .account_roles(account_id)
+            .await
+            .map(|roles| json!({ "account_id": account_id, "roles": roles })),
+        AdminCommand::GrantRole { account_id, role } => ctx
+            .auth
+            .grant_role(account_id, role)
+            .await
+            .map(|_| json!({ "account_id": account_id, "granted": role })),
+        AdminCommand::RevokeRole { account_id, role } => ctx
+            .auth
+            .revoke_role(account_id, role)
+            .await
+            .map(|_| json!({ "account_id": account_id, "revoked": role })),
+        AdminCommand::BanAccount { account_id } => {
+            ctx.auth.ban_account(account_id).await.map(|_| {
+                let closed = ctx
+                    .registry
+                    .end_account_sessions(account_id, None, "Account banned");
+                json!({ "account_id": account_id, "banned": true, "sessions_closed": closed })
+            })
+        }
+        AdminCommand::ListLockouts => ctx.auth.lockouts().await.map(|lockouts| json!(lockouts)),
+        AdminCommand::UnlockAccount { account_id } => ctx
+            .auth
+            .unlock_account(account_id)
+            .await
+            .map(|unlocked| json!({ "account_id": account_id, "unlocked": unlocked })),
+        AdminCommand::ListSessions { account_id } => {
+            let sessions = ctx.registry.account_sessions(account_id);
+            Ok(json!({ "account_id": account_id, "sessions": sessions }))
+        }
+        AdminCommand::EndSession { session_id } => {
+            let ended = end_session(ctx, session_id, "Session ended by an administrator").await;
+            Ok(json!({ "session_id": session_id, "ended": ended }))
+        }
+        AdminCommand::ConnectionStats => {
+            let (_, authenticated) = ctx.registry.connection_counts();
+            let stats = ctx.admission.stats(CONNECTION_STATS_TOP_ADDRESSES);
+            Ok(json!({ "authenticated": authenticated, "connections": stats }))
+        }
+    };
+
+    let response = match result {
+        Ok(result) => ServerMessage::AdminResult { result },
+        Err(e) => ServerMessage::error(
+            ErrorCode::AdminCommandFailed,
+            format!("Admin command failed: {}", e),
+        ),
+    };
+
+    ctx.ws_sender.send(ctx.reply(&response)?).await?;
+    Ok(())
+}
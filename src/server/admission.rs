@@ -0,0 +1,187 @@
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+/// Why a new connection was turned away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Refusal {
+    ServerFull,
+    TooManyFromAddress,
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Refusal::ServerFull => write!(f, "Server is full"),
+            Refusal::TooManyFromAddress => write!(f, "Too many connections from your address"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counts {
+    total: usize,
+    by_ip: HashMap<IpAddr, usize>,
+}
+
+/// Number of connections from one address, as shown to admins.
+#[derive(Serialize)]
+pub struct AddressConnections {
+    pub ip: IpAddr,
+    pub connections: usize,
+}
+
+/// Snapshot of the admitted connections and the limits they are held to.
+#[derive(Serialize)]
+pub struct ConnectionStats {
+    pub open: usize,
+    pub max_connections: usize,
+    pub max_per_ip: usize,
+    /// Addresses with the most connections first.
+    pub by_ip: Vec<AddressConnections>,
+}
+
+/// Caps the number of concurrent connections, overall and per client address. A connection
+/// counts from the moment it is accepted, handshake included, until its task ends.
+pub struct Admission {
+    max_connections: usize,
+    max_per_ip: usize,
+    counts: Mutex<Counts>,
+}
+
+/// Holds an admitted connection's place; dropping it frees the place again.
+pub struct AdmissionGuard {
+    admission: Arc<Admission>,
+    ip: IpAddr,
+}
+
+impl Admission {
+    pub fn new(max_connections: usize, max_per_ip: usize) -> Self {
+        Admission {
+            max_connections,
+            max_per_ip,
+            counts: Mutex::new(Counts::default()),
+        }
+    }
+
+    pub fn try_admit(self: &Arc<Self>, ip: IpAddr) -> Result<AdmissionGuard, Refusal> {
+        let mut counts = self.counts.lock().unwrap();
+        if counts.total >= self.max_connections {
+            return Err(Refusal::ServerFull);
+        }
+        if counts.by_ip.get(&ip).copied().unwrap_or(0) >= self.max_per_ip {
+            return Err(Refusal::TooManyFromAddress);
+        }
+        *counts.by_ip.entry(ip).or_insert(0) += 1;
+        counts.total += 1;
+
+        Ok(AdmissionGuard {
+            admission: Arc::clone(self),
+            ip,
+        })
+    }
+
+    /// Current counts, listing at most `top` addresses.
+    pub fn stats(&self, top: usize) -> ConnectionStats {
+        let counts = self.counts.lock().unwrap();
+        let mut by_ip: Vec<AddressConnections> = counts
+            .by_ip
+            .iter()
+            .map(|(ip, connections)| AddressConnections {
+                ip: *ip,
+                connections: *connections,
+            })
+            .collect();
+        by_ip.sort_by_key(|address| Reverse(address.connections));
+        by_ip.truncate(top);
+
+        ConnectionStats {
+            open: counts.total,
+            max_connections: self.max_connections,
+            max_per_ip: self.max_per_ip,
+            by_ip,
+        }
+    }
+
+    fn release(&self, ip: IpAddr) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.total = counts.total.saturating_sub(1);
+        if let Some(from_ip) = counts.by_ip.get_mut(&ip) {
+            *from_ip -= 1;
+            if *from_ip == 0 {
+                counts.by_ip.remove(&ip);
+            }
+        }
+    }
+}
+
+impl Drop for AdmissionGuard {
+    fn drop(&mut self) {
+        self.admission.release(self.ip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([198, 51, 100, last])
+    }
+
+    #[test]
+    fn caps_connections_per_address() {
+        let admission = Arc::new(Admission::new(10, 2));
+        let _first = admission.try_admit(ip(1)).unwrap();
+        let _second = admission.try_admit(ip(1)).unwrap();
+
+        assert_eq!(
+            admission.try_admit(ip(1)).err(),
+            Some(Refusal::TooManyFromAddress)
+        );
+        assert!(admission.try_admit(ip(2)).is_ok());
+    }
+
+    #[test]
+    fn caps_connections_overall() {
+        let admission = Arc::new(Admission::new(2, 2));
+        let _first = admission.try_admit(ip(1)).unwrap();
+        let _second = admission.try_admit(ip(2)).unwrap();
+
+        assert_eq!(admission.try_admit(ip(3)).err(), Some(Refusal::ServerFull));
+    }
+
+    #[test]
+    fn dropping_the_guard_frees_the_place() {
+        let admission = Arc::new(Admission::new(1, 1));
+        let guard = admission.try_admit(ip(1)).unwrap();
+        assert!(admission.try_admit(ip(1)).is_err());
+
+        drop(guard);
+        let stats = admission.stats(10);
+        assert_eq!(stats.open, 0);
+        assert!(stats.by_ip.is_empty());
+        assert!(admission.try_admit(ip(1)).is_ok());
+    }
+
+    #[test]
+    fn lists_the_busiest_addresses_first() {
+        let admission = Arc::new(Admission::new(10, 5));
+        let _guards: Vec<AdmissionGuard> = [1, 2, 2, 3, 3, 3]
+            .into_iter()
+            .map(|last| admission.try_admit(ip(last)).unwrap())
+            .collect();
+
+        let stats = admission.stats(2);
+        assert_eq!(stats.open, 6);
+        let top: Vec<(IpAddr, usize)> = stats
+            .by_ip
+            .iter()
+            .map(|address| (address.ip, address.connections))
+            .collect();
+        assert_eq!(top, vec![(ip(3), 3), (ip(2), 2)]);
+    }
+}
@@ -0,0 +1,298 @@
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::{header, HeaderValue, StatusCode};
+
+use crate::auth::{AuthError, AuthService, Claims, TokenKind};
+use crate::message::{Compression, Encoding};
+
+/// Prefix of a `Sec-WebSocket-Protocol` entry carrying an access token, e.g. `bearer.<jwt>`.
+const BEARER_PROTOCOL_PREFIX: &str = "bearer.";
+/// Prefix of a `Sec-WebSocket-Protocol` entry carrying the server password.
+const PASSWORD_PROTOCOL_PREFIX: &str = "password.";
+
+/// What the handshake learned about the client: its device, the encoding it asked for and,
+/// if it presented a valid access token, its session.
+#[derive(Default)]
+pub(super) struct HandshakeSession {
+    pub user_agent: Option<String>,
+    pub encoding: Encoding,
+    pub claims: Option<Claims>,
+    pub access_token: Option<String>,
+}
+
+/// Credentials a client presented while opening the socket.
+#[derive(Default)]
+struct Credentials {
+    token: Option<String>,
+    password: Option<String>,
+}
+
+/// Checks the upgrade request for a server password or an access token before the socket is
+/// accepted.
+///
+/// Credentials are read from the `Authorization` header (`Bearer <jwt>` or
+/// `Password <password>`), from `Sec-WebSocket-Protocol` entries (`bearer.<jwt>` or
+/// `password.<password>`) for browsers that cannot set headers, or from the `token` and
+/// `password` query parameters. A valid access token also satisfies the server password and
+/// authenticates the connection straight away.
+///
+/// Clients without credentials get `401 Unauthorized`, clients with wrong or revoked
+/// credentials `403 Forbidden`.
+#[allow(clippy::result_large_err)]
+pub(super) fn authenticate(
+    request: &Request,
+    mut response: Response,
+    auth: &AuthService,
+    server_password: Option<&str>,
+    compression: Option<Compression>,
+    session: &mut HandshakeSession,
+) -> Result<Response, ErrorResponse> {
+    session.user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
+    let credentials = read_credentials(request);
+
+    if let Some(token) = credentials.token {
+        // Callbacks cannot await, so the worker hands its other tasks off while the
+        // revocation lookup blocks
+        let validated =
+            tokio::task::block_in_place(|| auth.validate_token_blocking(&token, TokenKind::Access));
+        match validated {
+            Ok(claims) => {
+                session.claims = Some(claims);
+                session.access_token = Some(token);
+            }
+            Err(AuthError::InvalidToken | AuthError::Token(_)) => {
+                return Err(reject(StatusCode::UNAUTHORIZED, "Invalid or expired token"));
+            }
+            Err(AuthError::TokenRevoked | AuthError::AccountInactive) => {
+                return Err(reject(StatusCode::FORBIDDEN, "Token has been revoked"));
+            }
+            Err(e) => {
+                eprintln!("Error validating handshake token: {}", e);
+                return Err(reject(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "Unable to validate token",
+                ));
+            }
+        }
+    } else if let Some(expected) = server_password {
+        match credentials.password {
+            None => return Err(reject(StatusCode::UNAUTHORIZED, "Credentials required")),
+            Some(password) if !constant_time_eq(password.as_bytes(), expected.as_bytes()) => {
+                return Err(reject(StatusCode::FORBIDDEN, "Invalid server password"));
+            }
+            Some(_) => {}
+        }
+    }
+
+    if let Some(protocol) = select_protocol(request, compression) {
+        session.encoding = Encoding::from_protocol(&protocol, compression).unwrap_or_default();
+        if let Ok(value) = HeaderValue::from_str(&protocol) {
+            response
+                .headers_mut()
+                .insert(header::SEC_WEBSOCKET_PROTOCOL, value);
+        }
+    }
+
+    Ok(response)
+}
+
+fn read_credentials(request: &Request) -> Credentials {
+    let mut credentials = Credentials::default();
+
+    if let Some(value) = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+    {
+        match value.split_once(' ') {
+            Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") => {
+                credentials.token = Some(token.trim().to_string());
+            }
+            Some((scheme, password)) if scheme.eq_ignore_ascii_case("password") => {
+                credentials.password = Some(password.trim().to_string());
+            }
+            _ => {}
+        }
+    }
+
+    for protocol in offered_protocols(request) {
+        if let Some(token) = protocol.strip_prefix(BEARER_PROTOCOL_PREFIX) {
+            credentials.token.get_or_insert_with(|| token.to_string());
+        } else if let Some(password) = protocol.strip_prefix(PASSWORD_PROTOCOL_PREFIX) {
+            credentials
+                .password
+                .get_or_insert_with(|| password.to_string());
+        }
+    }
+
+    if let Some(query) = request.uri().query() {
+        for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+            match key.as_ref() {
+                "token" => {
+                    credentials.token.get_or_insert_with(|| value.into_owned());
+                }
+                "password" => {
+                    credentials
+                        .password
+                        .get_or_insert_with(|| value.into_owned());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    credentials
+}
+
+fn offered_protocols(request: &Request) -> impl Iterator<Item = &str> {
+    request
+        .headers()
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+}
+
+/// Picks the first offered encoding subprotocol (`rorust.json` or `rorust.msgpack`, with
+/// `.deflate` if compression is enabled), so clients list them in order of preference.
+/// Browsers drop the connection unless the server echoes one of the offered subprotocols,
+/// so without an encoding the first one that does not carry credentials is selected and the
+/// connection uses JSON.
+fn select_protocol(request: &Request, compression: Option<Compression>) -> Option<String> {
+    offered_protocols(request)
+        .find(|p| Encoding::from_protocol(p, compression).is_some())
+        .or_else(|| {
+            offered_protocols(request).find(|p| {
+                !p.starts_with(BEARER_PROTOCOL_PREFIX) && !p.starts_with(PASSWORD_PROTOCOL_PREFIX)
+            })
+        })
+        .map(str::to_string)
+}
+
+fn reject(status: StatusCode, reason: &str) -> ErrorResponse {
+    let mut response = ErrorResponse::new(Some(reason.to_string()));
+    *response.status_mut() = status;
+    if status == StatusCode::UNAUTHORIZED {
+        response
+            .headers_mut()
+            .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    }
+    response
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(uri: &str, headers: &[(header::HeaderName, &str)]) -> Request {
+        let mut builder = Request::builder().uri(uri);
+        for (name, value) in headers {
+            builder = builder.header(name, *value);
+        }
+        builder.body(()).unwrap()
+    }
+
+    #[test]
+    fn reads_credentials_from_the_authorization_header() {
+        let bearer = request("/", &[(header::AUTHORIZATION, "Bearer abc.def")]);
+        assert_eq!(read_credentials(&bearer).token.as_deref(), Some("abc.def"));
+
+        let password = request("/", &[(header::AUTHORIZATION, "password hunter2")]);
+        let credentials = read_credentials(&password);
+        assert_eq!(credentials.password.as_deref(), Some("hunter2"));
+        assert_eq!(credentials.token, None);
+    }
+
+    #[test]
+    fn reads_credentials_from_subprotocols_and_the_query() {
+        let protocols = request(
+            "/",
+            &[(
+                header::SEC_WEBSOCKET_PROTOCOL,
+                "rorust.json, bearer.abc, password.hunter2",
+            )],
+        );
+        let credentials = read_credentials(&protocols);
+        assert_eq!(credentials.token.as_deref(), Some("abc"));
+        assert_eq!(credentials.password.as_deref(), Some("hunter2"));
+
+        let query = request("/?token=a%2Bb&password=pw", &[]);
+        let credentials = read_credentials(&query);
+        assert_eq!(credentials.token.as_deref(), Some("a+b"));
+        assert_eq!(credentials.password.as_deref(), Some("pw"));
+    }
+
+    #[test]
+    fn prefers_the_header_over_other_credentials() {
+        let request = request(
+            "/?token=from-query",
+            &[
+                (header::AUTHORIZATION, "Bearer from-header"),
+                (header::SEC_WEBSOCKET_PROTOCOL, "bearer.from-protocol"),
+            ],
+        );
+        assert_eq!(
+            read_credentials(&request).token.as_deref(),
+            Some("from-header")
+        );
+    }
+
+    #[test]
+    fn selects_the_first_known_encoding() {
+        let request = request(
+            "/",
+            &[(
+                header::SEC_WEBSOCKET_PROTOCOL,
+                "bearer.abc, rorust.msgpack, rorust.json",
+            )],
+        );
+        assert_eq!(
+            select_protocol(&request, None).as_deref(),
+            Some("rorust.msgpack")
+        );
+    }
+
+    #[test]
+    fn offers_compressed_encodings_only_when_enabled() {
+        let compression = Some(Compression { threshold: 1024 });
+        let request = request(
+            "/",
+            &[(
+                header::SEC_WEBSOCKET_PROTOCOL,
+                "bearer.abc, rorust.msgpack.deflate, rorust.json",
+            )],
+        );
+
+        assert_eq!(
+            select_protocol(&request, compression).as_deref(),
+            Some("rorust.msgpack.deflate")
+        );
+        assert_eq!(
+            select_protocol(&request, None).as_deref(),
+            Some("rorust.json")
+        );
+    }
+
+    #[test]
+    fn echoes_an_unknown_subprotocol_but_never_credentials() {
+        let unknown = request("/", &[(header::SEC_WEBSOCKET_PROTOCOL, "bearer.abc, chat")]);
+        assert_eq!(select_protocol(&unknown, None).as_deref(), Some("chat"));
+
+        let credentials_only = request(
+            "/",
+            &[(header::SEC_WEBSOCKET_PROTOCOL, "bearer.abc, password.pw")],
+        );
+        assert_eq!(select_protocol(&credentials_only, None), None);
+        assert_eq!(select_protocol(&request("/", &[]), None), None);
+    }
+}
@@ -0,0 +1,197 @@
+use hyper::body::Incoming;
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde_json::json;
+use std::convert::Infallible;
+use std::fmt::Display;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+use super::ServerState;
+use crate::game::GameManager;
+
+/// How long `/readyz` waits for a database connection before reporting the server unready.
+const READY_DB_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Serves the plain HTTP routes used by load balancers, the web lobby and monitoring:
+///
+/// - `GET /healthz`: the process is up
+/// - `GET /readyz`: the server accepts players, i.e. it is not shutting down and both the
+///   database and the queue are reachable
+/// - `GET /lobby`: playable game types and the public rooms with their seats
+/// - `GET /metrics`: Prometheus text exposition of the server's counters and gauges
+pub(super) async fn serve(listener: TcpListener, state: Arc<ServerState>) {
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                eprintln!("Error accepting HTTP connection: {}", e);
+                continue;
+            }
+        };
+        let state = Arc::clone(&state);
+
+        tokio::spawn(async move {
+            let service = service_fn(move |request| route(request, Arc::clone(&state)));
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                eprintln!("Error serving HTTP connection: {}", e);
+            }
+        });
+    }
+}
+
+async fn route(
+    request: Request<Incoming>,
+    state: Arc<ServerState>,
+) -> Result<Response<String>, Infallible> {
+    let response = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/healthz") => respond(StatusCode::OK, "text/plain", "ok".to_string()),
+        (&Method::GET, "/readyz") => readiness(&state).await,
+        (&Method::GET, "/lobby") => lobby(&state).await,
+        (&Method::GET, "/metrics") => metrics(&state).await,
+        _ => respond(StatusCode::NOT_FOUND, "text/plain", "not found".to_string()),
+    };
+    Ok(response)
+}
+
+fn respond(status: StatusCode, content_type: &'static str, body: String) -> Response<String> {
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+    response
+}
+
+async fn readiness(state: &ServerState) -> Response<String> {
+    let unready = |reason: &str| {
+        respond(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "text/plain",
+            reason.to_string(),
+        )
+    };
+
+    if state.shutting_down.load(Ordering::Relaxed) {
+        return unready("shutting down");
+    }
+    if !state.db_queue.is_connected() {
+        return unready("queue unavailable");
+    }
+
+    let db_pool = Arc::clone(&state.db_pool);
+    let database_up =
+        tokio::task::spawn_blocking(move || db_pool.pool.get_timeout(READY_DB_TIMEOUT).is_ok())
+            .await
+            .unwrap_or(false);
+    if !database_up {
+        return unready("database unavailable");
+    }
+
+    respond(StatusCode::OK, "text/plain", "ready".to_string())
+}
+
+async fn lobby(state: &ServerState) -> Response<String> {
+    let rooms: Vec<_> = state
+        .game_manager
+        .room_summaries()
+        .await
+        .into_iter()
+        .filter(|room| !room.private)
+        .collect();
+    let game_types: Vec<String> = GameManager::playable_game_types()
+        .iter()
+        .map(|game_type| game_type.to_db_string().to_string())
+        .collect();
+
+    let body = json!({ "game_types": game_types, "rooms": rooms });
+    respond(StatusCode::OK, "application/json", body.to_string())
+}
+
+async fn metrics(state: &ServerState) -> Response<String> {
+    let (open, authenticated) = state.registry.connection_counts();
+    let rooms = state.game_manager.room_summaries().await;
+    let mut out = String::new();
+
+    write_metric(
+        &mut out,
+        "rorust_connections_accepted_total",
+        "counter",
+        "WebSocket connections accepted since start",
+        state.metrics.connections_accepted(),
+    );
+    write_metric(
+        &mut out,
+        "rorust_connections_refused_total",
+        "counter",
+        "WebSocket connections refused by the connection limits",
+        state.metrics.connections_refused(),
+    );
+    write_metric(
+        &mut out,
+        "rorust_connections_open",
+        "gauge",
+        "Open WebSocket connections",
+        open,
+    );
+    write_metric(
+        &mut out,
+        "rorust_connections_authenticated",
+        "gauge",
+        "Open WebSocket connections logged in to an account",
+        authenticated,
+    );
+    write_metric(
+        &mut out,
+        "rorust_messages_received_total",
+        "counter",
+        "Client messages decoded since start",
+        state.metrics.messages_received(),
+    );
+    write_metric(
+        &mut out,
+        "rorust_messages_rejected_total",
+        "counter",
+        "Client frames that could not be decoded",
+        state.metrics.messages_rejected(),
+    );
+    write_metric(
+        &mut out,
+        "rorust_players_seated",
+        "gauge",
+        "Players seated in a room",
+        rooms.iter().map(|room| room.players).sum::<usize>(),
+    );
+
+    out.push_str("# HELP rorust_rooms_open Open rooms by game type\n");
+    out.push_str("# TYPE rorust_rooms_open gauge\n");
+    let mut game_types: Vec<&str> = rooms.iter().map(|room| room.game_type.as_str()).collect();
+    game_types.dedup();
+    for game_type in game_types {
+        let count = rooms
+            .iter()
+            .filter(|room| room.game_type == game_type)
+            .count();
+        out.push_str(&format!(
+            "rorust_rooms_open{{game_type=\"{}\"}} {}\n",
+            game_type, count
+        ));
+    }
+
+    respond(StatusCode::OK, "text/plain; version=0.0.4", out)
+}
+
+fn write_metric(out: &mut String, name: &str, kind: &str, help: &str, value: impl Display) {
+    out.push_str(&format!(
+        "# HELP {} {}\n# TYPE {} {}\n{} {}\n",
+        name, help, name, kind, name, value
+    ));
+}
@@ -0,0 +1,46 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters of the WebSocket server, exported on `/metrics`. Gauges such as open connections
+/// or rooms are read from the registry and the game manager at scrape time instead.
+#[derive(Default)]
+pub struct Metrics {
+    connections_accepted: AtomicU64,
+    connections_refused: AtomicU64,
+    messages_received: AtomicU64,
+    messages_rejected: AtomicU64,
+}
+
+impl Metrics {
+    pub fn connection_accepted(&self) {
+        self.connections_accepted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_refused(&self) {
+        self.connections_refused.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn message_received(&self) {
+        self.messages_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A frame that could not be decoded into a client message.
+    pub fn message_rejected(&self) {
+        self.messages_rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connections_accepted(&self) -> u64 {
+        self.connections_accepted.load(Ordering::Relaxed)
+    }
+
+    pub fn connections_refused(&self) -> u64 {
+        self.connections_refused.load(Ordering::Relaxed)
+    }
+
+    pub fn messages_received(&self) -> u64 {
+        self.messages_received.load(Ordering::Relaxed)
+    }
+
+    pub fn messages_rejected(&self) -> u64 {
+        self.messages_rejected.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,1583 @@
+mod admin;
+mod admission;
+mod handshake;
+mod http;
+mod metrics;
+mod rate_limit;
+pub mod registry;
+mod session;
+mod tls;
+
+use crate::auth::policy;
+use crate::auth::{
+    AuthError, AuthService, Claims, GuestSession, Registration, TokenKind, TokenPair,
+};
+use crate::config;
+use crate::db::DbPool;
+use crate::game::{game_types::*, DisconnectReason, GameManager, RoomSettings};
+use crate::message::{
+    encode_server_message, parse_client_message, parse_client_message_msgpack, peek_request_id,
+    ClientMessage, Compression, Encoding, ErrorCode, MessageError, ServerEnvelope, ServerMessage,
+    MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+use crate::queues::db_queue::DbQueue;
+use admission::{Admission, AdmissionGuard, Refusal};
+use futures::{
+    stream::{SplitSink, SplitStream},
+    SinkExt, StreamExt,
+};
+use metrics::Metrics;
+use rate_limit::{RateDecision, RateLimiter, RateLimits};
+use registry::{ConnectionRegistry, EndedSession, PushSequence, SessionLimitPolicy};
+use serde_json::json;
+use session::SessionStore;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot, Semaphore};
+use tokio::task::JoinSet;
+use tokio::time::MissedTickBehavior;
+use tokio_rustls::TlsAcceptor;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, Message, WebSocketConfig};
+use tokio_tungstenite::tungstenite::Error as WsError;
+use tokio_tungstenite::{accept_async_with_config, accept_hdr_async_with_config, WebSocketStream};
+use uuid::Uuid;
+
+/// Messages queued for a socket before its writer task applies backpressure.
+const OUTBOUND_QUEUE_SIZE: usize = 256;
+
+/// Refused connections that may be in their handshake at once. Further refusals are dropped
+/// without a close frame.
+const MAX_PENDING_REFUSALS: usize = 64;
+
+/// Time a refused client gets to finish the handshake and receive its close frame.
+const REFUSAL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time an admitted client gets for each of the TLS and WebSocket handshakes, so that idle
+/// clients cannot hold on to their admission slot.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often shutdown checks whether the running rounds have finished.
+const ROUND_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// State shared by all connections.
+struct ServerState {
+    game_manager: Arc<GameManager>,
+    auth: Arc<AuthService>,
+    sessions: Arc<SessionStore>,
+    registry: Arc<ConnectionRegistry>,
+    resume_grace: Duration,
+    ping_interval: Duration,
+    idle_timeout: Duration,
+    max_message_bytes: usize,
+    /// Offered to clients asking for a `.deflate` subprotocol, `None` if disabled.
+    compression: Option<Compression>,
+    rate_limits: RateLimits,
+    server_password: Option<String>,
+    /// Set when the server terminates TLS itself and serves `wss://`.
+    tls: Option<TlsAcceptor>,
+    db_pool: Arc<DbPool>,
+    db_queue: Arc<DbQueue>,
+    metrics: Metrics,
+    /// Set once shutdown starts, so that `/readyz` takes the server out of rotation.
+    shutting_down: AtomicBool,
+    admission: Arc<Admission>,
+    refusal_slots: Arc<Semaphore>,
+}
+
+/// Byte stream a WebSocket runs over: plain TCP, or TLS on top of it.
+trait Transport: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + 'static> Transport for T {}
+
+struct ConnectionContext {
+    /// Queue of the socket's writer task. Rooms push to the same queue through the registry.
+    ws_sender: mpsc::Sender<Message>,
+    /// Wire format picked during the handshake.
+    encoding: Encoding,
+    pushes: PushSequence,
+    /// `request_id` of the client message being handled, echoed on the reply.
+    request_id: Option<String>,
+    /// Set once the client's `Hello` was accepted.
+    greeted: bool,
+    game_manager: Arc<GameManager>,
+    auth: Arc<AuthService>,
+    sessions: Arc<SessionStore>,
+    registry: Arc<ConnectionRegistry>,
+    admission: Arc<Admission>,
+    connection_id: Uuid,
+    addr: SocketAddr,
+    claims: Option<Claims>,
+    access_token: Option<String>,
+    player_id: String,
+    room_id: String,
+}
+
+impl ConnectionContext {
+    /// Encodes a direct reply to the client message being handled.
+    fn reply(&self, message: &ServerMessage) -> Result<Message, MessageError> {
+        let envelope = ServerEnvelope::reply(message, self.request_id.as_deref());
+        encode_server_message(&envelope, self.encoding)
+    }
+
+    /// Encodes a message the server sends on its own.
+    fn push(&self, message: &ServerMessage) -> Result<Message, MessageError> {
+        let envelope = ServerEnvelope::push(message, self.pushes.next());
+        encode_server_message(&envelope, self.encoding)
+    }
+}
+
+/// Runs the TLS handshake first if the server terminates TLS itself, then either serves the
+/// connection or closes it again if it was refused.
+async fn accept_connection(
+    stream: TcpStream,
+    addr: SocketAddr,
+    state: Arc<ServerState>,
+    admitted: Result<AdmissionGuard, Refusal>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match state.tls.clone() {
+        Some(acceptor) => {
+            let stream = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await??;
+            serve_socket(stream, addr, state, admitted).await
+        }
+        None => serve_socket(stream, addr, state, admitted).await,
+    }
+}
+
+async fn serve_socket<S: Transport>(
+    stream: S,
+    addr: SocketAddr,
+    state: Arc<ServerState>,
+    admitted: Result<AdmissionGuard, Refusal>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match admitted {
+        // The guard keeps the connection's place until the connection is done
+        Ok(_guard) => handle_connection(stream, addr, state).await,
+        Err(refusal) => refuse_connection(stream, &state, refusal).await,
+    }
+}
+
+/// Completes the WebSocket handshake only to close the socket with `1013 Try Again Later`,
+/// so that clients can tell a full server from a network error and back off.
+async fn refuse_connection<S: Transport>(
+    stream: S,
+    state: &ServerState,
+    refusal: Refusal,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let ws_config = WebSocketConfig {
+        max_message_size: Some(state.max_message_bytes),
+        max_frame_size: Some(state.max_message_bytes),
+        ..WebSocketConfig::default()
+    };
+    let mut ws_stream = accept_async_with_config(stream, Some(ws_config)).await?;
+    let frame = CloseFrame {
+        code: CloseCode::Again,
+        reason: refusal.to_string().into(),
+    };
+    ws_stream.close(Some(frame)).await?;
+    Ok(())
+}
+
+async fn handle_connection<S: Transport>(
+    stream: S,
+    addr: SocketAddr,
+    state: Arc<ServerState>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut session = handshake::HandshakeSession::default();
+    let ws_config = WebSocketConfig {
+        max_message_size: Some(state.max_message_bytes),
+        max_frame_size: Some(state.max_message_bytes),
+        ..WebSocketConfig::default()
+    };
+    // The error type is fixed by tungstenite's handshake `Callback`.
+    #[allow(clippy::result_large_err)]
+    let handshake = accept_hdr_async_with_config(
+        stream,
+        |request: &_, response| {
+            handshake::authenticate(
+                request,
+                response,
+                &state.auth,
+                state.server_password.as_deref(),
+                state.compression,
+                &mut session,
+            )
+        },
+        Some(ws_config),
+    );
+    let ws_stream = tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake).await??;
+    let (ws_sender, ws_receiver) = ws_stream.split();
+
+    println!("Connection established from {}", addr);
+
+    let (outbound, outbound_queue) = mpsc::channel(OUTBOUND_QUEUE_SIZE);
+    spawn_writer(ws_sender, outbound_queue);
+
+    let connection_id = Uuid::new_v4();
+    let pushes = PushSequence::default();
+    let closed = state.registry.register(
+        connection_id,
+        addr.ip(),
+        session.user_agent,
+        session.encoding,
+        pushes.clone(),
+        outbound.clone(),
+    );
+
+    let mut ctx = ConnectionContext {
+        ws_sender: outbound,
+        encoding: session.encoding,
+        pushes,
+        request_id: None,
+        greeted: false,
+        game_manager: Arc::clone(&state.game_manager),
+        auth: Arc::clone(&state.auth),
+        sessions: Arc::clone(&state.sessions),
+        registry: Arc::clone(&state.registry),
+        admission: Arc::clone(&state.admission),
+        connection_id,
+        addr,
+        claims: None,
+        access_token: None,
+        player_id: String::new(),
+        room_id: String::new(),
+    };
+
+    let result = async {
+        if let (Some(claims), Some(access_token)) = (session.claims, session.access_token) {
+            start_authenticated_session(&mut ctx, claims, access_token).await?;
+        }
+        process_messages(&mut ctx, ws_receiver, closed, &state).await
+    }
+    .await;
+
+    state.registry.remove(connection_id);
+    let reason = result.as_ref().ok().copied().flatten();
+    handle_disconnect(&ctx, state.resume_grace, reason);
+    result.map(|_| ())
+}
+
+/// Owns the socket's sink and writes everything queued for the connection, both replies of
+/// the connection's own task and updates pushed by rooms. The writer finishes once the
+/// queue is flushed and the connection is gone from the registry.
+fn spawn_writer<S: Transport>(
+    mut ws_sender: SplitSink<WebSocketStream<S>, Message>,
+    mut outbound: mpsc::Receiver<Message>,
+) {
+    tokio::spawn(async move {
+        while let Some(message) = outbound.recv().await {
+            let closing = message.is_close();
+            if ws_sender.send(message).await.is_err() || closing {
+                break;
+            }
+        }
+        let _ = ws_sender.close().await;
+    });
+}
+
+/// Sets up a session for a connection that authenticated during the handshake.
+async fn start_authenticated_session(
+    ctx: &mut ConnectionContext,
+    claims: Claims,
+    access_token: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if attach_account(ctx, vec![claims.clone()]).await {
+        ctx.player_id = claims.sub.clone();
+        ctx.registry.bind_player(ctx.connection_id, &ctx.player_id);
+        ctx.claims = Some(claims);
+        ctx.access_token = Some(access_token);
+        return Ok(());
+    }
+
+    let response = ServerMessage::SessionLimitReached {
+        max_sessions: ctx.registry.max_per_account(),
+    };
+    ctx.ws_sender.send(ctx.push(&response)?).await?;
+    Ok(())
+}
+
+/// Reads client messages until the socket closes, the session is ended from elsewhere, the
+/// client stops answering heartbeats or keeps going over its rate limits. Returns the reason to remove the player from their room
+/// right away, if there is one.
+async fn process_messages<S: Transport>(
+    ctx: &mut ConnectionContext,
+    mut ws_receiver: SplitStream<WebSocketStream<S>>,
+    mut closed: oneshot::Receiver<String>,
+    state: &ServerState,
+) -> Result<Option<DisconnectReason>, Box<dyn std::error::Error>> {
+    let mut heartbeat = tokio::time::interval_at(
+        tokio::time::Instant::now() + state.ping_interval,
+        state.ping_interval,
+    );
+    heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut last_seen = tokio::time::Instant::now();
+    let mut limiter = RateLimiter::new(&state.rate_limits);
+
+    loop {
+        tokio::select! {
+            msg = ws_receiver.next() => {
+                let msg = match msg {
+                    Some(Ok(msg)) => msg,
+                    Some(Err(WsError::Capacity(e))) => {
+                        println!("Closing connection from {}: {}", ctx.addr, e);
+                        let frame = CloseFrame {
+                            code: CloseCode::Size,
+                            reason: "Message too large".into(),
+                        };
+                        ctx.ws_sender.send(Message::Close(Some(frame))).await?;
+                        break;
+                    }
+                    _ => break,
+                };
+                last_seen = tokio::time::Instant::now();
+                let client_message = match &msg {
+                    Message::Text(text) => parse_client_message(text).map_err(MessageError::from),
+                    Message::Binary(data) => parse_client_message_msgpack(data),
+                    // Pings are answered by tungstenite, pongs only count as a sign of life
+                    _ => continue,
+                };
+
+                // Frames that fail to parse count against the connection's limit as well
+                ctx.request_id = match &client_message {
+                    Ok(envelope) => envelope.request_id.clone(),
+                    Err(_) => peek_request_id(&msg),
+                };
+                let kind = client_message.as_ref().map_or("invalid", |m| m.message.kind());
+                match limiter.check(kind) {
+                    RateDecision::Allowed => {}
+                    RateDecision::Limited(retry_after) => {
+                        let response = ServerMessage::Error {
+                            code: ErrorCode::RateLimited,
+                            message: "Too many messages".to_string(),
+                            details: Some(json!({
+                                "message_type": kind,
+                                "retry_after_ms": retry_after.as_millis() as u64 + 1,
+                            })),
+                        };
+                        ctx.ws_sender
+                            .send(ctx.reply(&response)?)
+                            .await?;
+                        continue;
+                    }
+                    RateDecision::Disconnect => {
+                        println!("Disconnecting {}: rate limit exceeded", ctx.addr);
+                        close_with_reason(ctx, "Rate limit exceeded".to_string()).await?;
+                        break;
+                    }
+                }
+
+                match client_message {
+                    Ok(envelope) if !ctx.greeted => {
+                        state.metrics.message_received();
+                        if !greet(ctx, envelope.message, state).await? {
+                            break;
+                        }
+                    }
+                    Ok(envelope) => {
+                        state.metrics.message_received();
+                        if !handle_client_message(ctx, envelope.message).await? {
+                            break;
+                        }
+                    }
+                    Err(parse_error) => {
+                        state.metrics.message_rejected();
+                        handle_parse_error(ctx, parse_error).await?;
+                    }
+                }
+            }
+            reason = &mut closed => {
+                let reason = reason.unwrap_or_else(|_| "Session ended".to_string());
+                close_with_reason(ctx, reason).await?;
+                break;
+            }
+            _ = heartbeat.tick() => {
+                if last_seen.elapsed() >= state.idle_timeout {
+                    println!("Connection from {} timed out", ctx.addr);
+                    close_with_reason(ctx, "Idle timeout".to_string()).await?;
+                    return Ok(Some(DisconnectReason::IdleTimeout));
+                }
+                ctx.ws_sender.send(Message::Ping(Vec::new())).await?;
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Answers the client's `Hello`, which has to be its first message, with what the server
+/// supports. Returns `false` if the client was refused and the socket is closing.
+async fn greet(
+    ctx: &mut ConnectionContext,
+    message: ClientMessage,
+    state: &ServerState,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let ClientMessage::Hello {
+        protocol_version,
+        client_name,
+        capabilities,
+    } = message
+    else {
+        refuse_client(ctx, "Hello must be the first message".to_string()).await?;
+        return Ok(false);
+    };
+
+    if let Some(reason) = unsupported_version(protocol_version) {
+        refuse_client(ctx, reason).await?;
+        return Ok(false);
+    }
+
+    println!(
+        "{} speaks protocol {} ({}, capabilities: [{}])",
+        ctx.addr,
+        protocol_version,
+        client_name.as_deref().unwrap_or("unknown client"),
+        capabilities.join(", ")
+    );
+    ctx.greeted = true;
+
+    let response = welcome(state.compression);
+    ctx.ws_sender.send(ctx.reply(&response)?).await?;
+    Ok(true)
+}
+
+/// Why a client speaking `protocol_version` is refused, if it is.
+fn unsupported_version(protocol_version: u32) -> Option<String> {
+    if (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&protocol_version) {
+        return None;
+    }
+    Some(format!(
+        "Protocol version {} is not supported",
+        protocol_version
+    ))
+}
+
+fn welcome(compression: Option<Compression>) -> ServerMessage {
+    ServerMessage::Welcome {
+        protocol_version: PROTOCOL_VERSION,
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        game_types: GameManager::playable_game_types()
+            .iter()
+            .map(|game_type| game_type.to_db_string().to_string())
+            .collect(),
+        features: server_features(compression),
+    }
+}
+
+/// Optional features announced in `Welcome`.
+fn server_features(compression: Option<Compression>) -> Vec<String> {
+    let mut features = vec!["msgpack", "request_id", "resume", "guest", "private_rooms"];
+    if compression.is_some() {
+        features.push("deflate");
+    }
+    features.into_iter().map(str::to_string).collect()
+}
+
+/// Tells a client it cannot be served and closes the socket with `1008 Policy Violation`.
+async fn refuse_client(
+    ctx: &ConnectionContext,
+    reason: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("Refusing client {}: {}", ctx.addr, reason);
+    let response = ServerMessage::ProtocolRejected {
+        reason: reason.clone(),
+        min_version: MIN_PROTOCOL_VERSION,
+        max_version: PROTOCOL_VERSION,
+    };
+    ctx.ws_sender.send(ctx.reply(&response)?).await?;
+
+    let frame = CloseFrame {
+        code: CloseCode::Policy,
+        reason: reason.into(),
+    };
+    ctx.ws_sender.send(Message::Close(Some(frame))).await?;
+    Ok(())
+}
+
+/// Tells the client why its session is over and closes the socket.
+async fn close_with_reason(
+    ctx: &ConnectionContext,
+    reason: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let response = ServerMessage::SessionEnded { reason };
+    ctx.ws_sender.send(ctx.push(&response)?).await?;
+    ctx.ws_sender.send(Message::Close(None)).await?;
+    Ok(())
+}
+
+/// Keeps the player's seat for `resume_grace` after the socket closes so that a `Resume`
+/// from a new socket can pick the session up again. The player is removed from the room
+/// once the grace period passes without a resume, or right away if `reason` is given.
+fn handle_disconnect(
+    ctx: &ConnectionContext,
+    resume_grace: Duration,
+    reason: Option<DisconnectReason>,
+) {
+    if ctx.room_id.is_empty() || !ctx.sessions.detach(&ctx.player_id, ctx.connection_id) {
+        return;
+    }
+
+    let sessions = Arc::clone(&ctx.sessions);
+    let game_manager = Arc::clone(&ctx.game_manager);
+    let player_id = ctx.player_id.clone();
+    let room_id = ctx.room_id.clone();
+    let connection_id = ctx.connection_id;
+
+    tokio::spawn(async move {
+        if reason.is_none() {
+            let update = json!({ "event": "player_disconnected", "player_id": player_id });
+            game_manager.broadcast_to_room(&room_id, &update).await;
+
+            tokio::time::sleep(resume_grace).await;
+        }
+
+        if let Some(room_id) = sessions.expire(&player_id, connection_id) {
+            let reason = reason.unwrap_or(DisconnectReason::ResumeExpired);
+            game_manager.remove_player(room_id, player_id, reason).await;
+        }
+    });
+}
+
+async fn handle_client_message(
+    ctx: &mut ConnectionContext,
+    client_message: ClientMessage,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    if let Some(permission) = policy::required_permission(&client_message) {
+        let claims = if has_valid_session(ctx).await {
+            ctx.claims.as_ref()
+        } else {
+            None
+        };
+
+        if let Err(e) = policy::authorize(claims, permission) {
+            let response = ServerMessage::PermissionDenied {
+                permission,
+                reason: e.to_string(),
+            };
+            ctx.ws_sender.send(ctx.reply(&response)?).await?;
+            return Ok(true);
+        }
+    }
+
+    match client_message {
+        ClientMessage::Hello { .. } => {
+            let response =
+                ServerMessage::error(ErrorCode::UnexpectedMessage, "Hello was already received");
+            ctx.ws_sender.send(ctx.reply(&response)?).await?;
+        }
+        ClientMessage::Auth { username, password } => handle_auth(ctx, username, password).await?,
+        ClientMessage::Register {
+            username,
+            email,
+            password,
+            first_name,
+            last_name,
+            citizen_id,
+        } => {
+            let registration = Registration {
+                username,
+                email,
+                password,
+                first_name,
+                last_name,
+                citizen_id,
+            };
+            handle_register(ctx, registration).await?
+        }
+        ClientMessage::GuestLogin => handle_guest_login(ctx).await?,
+        ClientMessage::UpgradeGuest {
+            username,
+            email,
+            password,
+            first_name,
+            last_name,
+            citizen_id,
+        } => {
+            let registration = Registration {
+                username,
+                email,
+                password,
+                first_name,
+                last_name,
+                citizen_id,
+            };
+            handle_upgrade_guest(ctx, registration).await?
+        }
+        ClientMessage::GetBalance => handle_get_balance(ctx).await?,
+        ClientMessage::RefreshToken { refresh_token } => {
+            handle_refresh_token(ctx, refresh_token).await?
+        }
+        ClientMessage::Logout {
+            refresh_token,
+            all_devices,
+        } => handle_logout(ctx, refresh_token, all_devices).await?,
+        ClientMessage::Resume { token, last_seq } => handle_resume(ctx, token, last_seq).await?,
+        ClientMessage::ChangePassword {
+            current_password,
+            new_password,
+        } => handle_change_password(ctx, current_password, new_password).await?,
+        ClientMessage::RequestPasswordReset { email } => {
+            handle_request_password_reset(ctx, email).await?
+        }
+        ClientMessage::ResetPassword {
+            token,
+            new_password,
+        } => handle_reset_password(ctx, token, new_password).await?,
+        ClientMessage::ListSessions => handle_list_sessions(ctx).await?,
+        ClientMessage::EndSession { session_id } => handle_end_session(ctx, session_id).await?,
+        ClientMessage::GameAction { action, params } => {
+            handle_game_action(ctx, action, params).await?
+        }
+        ClientMessage::Quit => handle_quit(ctx).await?,
+        ClientMessage::SelectGame {
+            game_type,
+            high_limit,
+            free_play,
+        } => handle_select_game(ctx, game_type, high_limit, free_play).await?,
+        ClientMessage::CreatePrivateRoom { game_type } => {
+            handle_create_private_room(ctx, game_type).await?
+        }
+        ClientMessage::Admin { command } => admin::handle_admin_command(ctx, command).await?,
+    }
+    Ok(true)
+}
+
+async fn handle_auth(
+    ctx: &mut ConnectionContext,
+    username: String,
+    password: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let response = match ctx.auth.login(&username, &password, ctx.addr.ip()).await {
+        Ok(tokens) => start_session(ctx, tokens).await,
+        Err(AuthError::InvalidCredentials | AuthError::AccountInactive) => {
+            ServerMessage::AuthFailed
+        }
+        Err(AuthError::AccountLocked { locked_until }) => ServerMessage::AccountLocked {
+            locked_until: locked_until.and_utc().timestamp(),
+        },
+        Err(AuthError::LoginThrottled { retry_after_secs }) => {
+            ServerMessage::LoginThrottled { retry_after_secs }
+        }
+        Err(e) => {
+            eprintln!("Error authenticating {}: {}", username, e);
+            ServerMessage::AuthFailed
+        }
+    };
+
+    ctx.ws_sender.send(ctx.reply(&response)?).await?;
+    Ok(())
+}
+
+async fn handle_refresh_token(
+    ctx: &mut ConnectionContext,
+    refresh_token: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let response = match ctx.auth.refresh(&refresh_token).await {
+        Ok(tokens) => start_session(ctx, tokens).await,
+        Err(e) => {
+            println!("Refresh token rejected: {}", e);
+            ServerMessage::AuthFailed
+        }
+    };
+
+    ctx.ws_sender.send(ctx.reply(&response)?).await?;
+    Ok(())
+}
+
+async fn handle_logout(
+    ctx: &mut ConnectionContext,
+    refresh_token: Option<String>,
+    all_devices: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(claims) = ctx.claims.take() {
+        let result = if all_devices && !claims.guest {
+            match claims.account_id() {
+                Ok(account_id) => {
+                    let revoked = ctx.auth.revoke_account_tokens(account_id, "logout").await;
+                    ctx.registry.end_account_sessions(
+                        account_id,
+                        Some(ctx.connection_id),
+                        "Logged out on all devices",
+                    );
+                    revoked
+                }
+                Err(e) => Err(e),
+            }
+        } else {
+            let refresh_claims = match refresh_token {
+                Some(token) => ctx
+                    .auth
+                    .validate_token(&token, TokenKind::Refresh)
+                    .await
+                    .ok(),
+                None => None,
+            }
+            .filter(|refresh_claims| refresh_claims.sub == claims.sub);
+
+            match ctx.auth.revoke_token(&claims, "logout").await {
+                Ok(()) => match refresh_claims {
+                    Some(refresh_claims) => ctx.auth.revoke_token(&refresh_claims, "logout").await,
+                    None => Ok(()),
+                },
+                Err(e) => Err(e),
+            }
+        };
+
+        if let Err(e) = result {
+            eprintln!("Error revoking tokens for {}: {}", claims.username, e);
+        }
+    }
+    leave_room(ctx).await;
+    ctx.access_token = None;
+    ctx.player_id.clear();
+    ctx.registry.detach_account(ctx.connection_id);
+
+    ctx.ws_sender
+        .send(ctx.reply(&ServerMessage::LoggedOut)?)
+        .await?;
+    Ok(())
+}
+
+/// Changes the password of the logged in account. All sessions of the account, including
+/// this one, are revoked and have to log in again.
+async fn handle_change_password(
+    ctx: &mut ConnectionContext,
+    current_password: String,
+    new_password: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let valid = has_valid_session(ctx).await;
+    let account_id = match ctx.claims.as_ref().map(Claims::account_id) {
+        Some(Ok(account_id)) if valid => account_id,
+        _ => {
+            let response = ServerMessage::PasswordChangeFailed {
+                reason: "Not authenticated".to_string(),
+            };
+            ctx.ws_sender.send(ctx.reply(&response)?).await?;
+            return Ok(());
+        }
+    };
+
+    let response = match ctx
+        .auth
+        .change_password(account_id, &current_password, &new_password)
+        .await
+    {
+        Ok(()) => {
+            ctx.claims = None;
+            ctx.access_token = None;
+            ctx.registry.detach_account(ctx.connection_id);
+            ctx.registry
+                .end_account_sessions(account_id, None, "Password changed");
+            ServerMessage::PasswordChanged
+        }
+        Err(e @ (AuthError::InvalidCredentials | AuthError::InvalidRegistration { .. })) => {
+            ServerMessage::PasswordChangeFailed {
+                reason: e.to_string(),
+            }
+        }
+        Err(e) => {
+            eprintln!("Error changing password of account {}: {}", account_id, e);
+            ServerMessage::PasswordChangeFailed {
+                reason: "Password could not be changed".to_string(),
+            }
+        }
+    };
+
+    ctx.ws_sender.send(ctx.reply(&response)?).await?;
+    Ok(())
+}
+
+/// Always answers with `PasswordResetRequested`, whether or not the email is registered.
+async fn handle_request_password_reset(
+    ctx: &mut ConnectionContext,
+    email: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    ctx.auth.request_password_reset(&email);
+
+    ctx.ws_sender
+        .send(ctx.reply(&ServerMessage::PasswordResetRequested)?)
+        .await?;
+    Ok(())
+}
+
+async fn handle_reset_password(
+    ctx: &mut ConnectionContext,
+    token: String,
+    new_password: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let response = match ctx.auth.reset_password(&token, &new_password).await {
+        Ok(account_id) => {
+            ctx.registry.end_account_sessions(
+                account_id,
+                Some(ctx.connection_id),
+                "Password reset",
+            );
+            ServerMessage::PasswordChanged
+        }
+        Err(e @ (AuthError::InvalidResetToken | AuthError::InvalidRegistration { .. })) => {
+            ServerMessage::PasswordChangeFailed {
+                reason: e.to_string(),
+            }
+        }
+        Err(e) => {
+            eprintln!("Error resetting password: {}", e);
+            ServerMessage::PasswordChangeFailed {
+                reason: "Password could not be reset".to_string(),
+            }
+        }
+    };
+
+    ctx.ws_sender.send(ctx.reply(&response)?).await?;
+    Ok(())
+}
+
+async fn handle_list_sessions(
+    ctx: &mut ConnectionContext,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let valid = has_valid_session(ctx).await;
+    let response = match ctx.registry.account_of(ctx.connection_id) {
+        Some(account_id) if valid => ServerMessage::Sessions {
+            current_session_id: ctx.connection_id,
+            sessions: ctx.registry.account_sessions(account_id),
+        },
+        _ => ServerMessage::error(ErrorCode::Unauthorized, "Not authenticated"),
+    };
+
+    ctx.ws_sender.send(ctx.reply(&response)?).await?;
+    Ok(())
+}
+
+/// Ends another session of the player's own account.
+async fn handle_end_session(
+    ctx: &mut ConnectionContext,
+    session_id: Uuid,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let account_id = ctx.registry.account_of(ctx.connection_id);
+    let response = if account_id.is_none() || !has_valid_session(ctx).await {
+        ServerMessage::error(ErrorCode::Unauthorized, "Not authenticated")
+    } else if session_id == ctx.connection_id
+        || ctx.registry.account_of(session_id) != account_id
+        || !end_session(ctx, session_id, "Session ended from another device").await
+    {
+        ServerMessage::Error {
+            code: ErrorCode::UnknownSession,
+            message: "Unknown session".to_string(),
+            details: Some(json!({ "session_id": session_id })),
+        }
+    } else {
+        ServerMessage::SessionClosed { session_id }
+    };
+
+    ctx.ws_sender.send(ctx.reply(&response)?).await?;
+    Ok(())
+}
+
+async fn handle_register(
+    ctx: &mut ConnectionContext,
+    registration: Registration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let response = match ctx.auth.register(registration).await {
+        Ok(account) => ServerMessage::RegisterSuccess {
+            account_id: account.id,
+        },
+        Err(e) => registration_failed(e),
+    };
+
+    ctx.ws_sender.send(ctx.reply(&response)?).await?;
+    Ok(())
+}
+
+fn registration_failed(error: AuthError) -> ServerMessage {
+    let (field, taken) = match &error {
+        AuthError::UsernameTaken => ("username", true),
+        AuthError::EmailTaken => ("email", true),
+        AuthError::CitizenIdTaken => ("citizen_id", true),
+        AuthError::InvalidRegistration { field, .. } => (*field, false),
+        e => {
+            eprintln!("Error registering account: {}", e);
+            return ServerMessage::RegisterFailed {
+                reason: "Registration failed".to_string(),
+                field: None,
+                taken: false,
+            };
+        }
+    };
+    ServerMessage::RegisterFailed {
+        reason: error.to_string(),
+        field: Some(field.to_string()),
+        taken,
+    }
+}
+
+async fn handle_guest_login(ctx: &mut ConnectionContext) -> Result<(), Box<dyn std::error::Error>> {
+    let response = match ctx.auth.guest_login() {
+        Ok(guest) => start_guest_session(ctx, guest).await,
+        Err(e) => {
+            eprintln!("Error creating guest session: {}", e);
+            ServerMessage::AuthFailed
+        }
+    };
+
+    ctx.ws_sender.send(ctx.reply(&response)?).await?;
+    Ok(())
+}
+
+/// Turns the guest on this connection into a registered account. The guest leaves its
+/// free-play room and its play money is discarded.
+async fn handle_upgrade_guest(
+    ctx: &mut ConnectionContext,
+    registration: Registration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let valid = has_valid_session(ctx).await;
+    let guest = match ctx.claims.clone() {
+        Some(claims) if claims.guest && valid => claims,
+        _ => {
+            let response = ServerMessage::RegisterFailed {
+                reason: "Only guests can be upgraded".to_string(),
+                field: None,
+                taken: false,
+            };
+            ctx.ws_sender.send(ctx.reply(&response)?).await?;
+            return Ok(());
+        }
+    };
+
+    let response = match ctx.auth.upgrade_guest(&guest, registration).await {
+        Ok((account, tokens)) => {
+            leave_room(ctx).await;
+            match start_session(ctx, tokens).await {
+                ServerMessage::AuthSuccess {
+                    token,
+                    refresh_token,
+                    expires_in,
+                } => ServerMessage::GuestUpgraded {
+                    account_id: account.id,
+                    token,
+                    refresh_token,
+                    expires_in,
+                },
+                other => other,
+            }
+        }
+        Err(e) => registration_failed(e),
+    };
+
+    ctx.ws_sender.send(ctx.reply(&response)?).await?;
+    Ok(())
+}
+
+/// Only guests have a balance for now, and it is play money.
+async fn handle_get_balance(ctx: &mut ConnectionContext) -> Result<(), Box<dyn std::error::Error>> {
+    let balance = match &ctx.claims {
+        Some(claims) if claims.guest => ctx.auth.guest_balance(claims),
+        _ => None,
+    };
+
+    let response = match balance {
+        Some(balance) => ServerMessage::Balance {
+            balance,
+            play_money: true,
+        },
+        None => ServerMessage::error(ErrorCode::BalanceUnavailable, "No balance available"),
+    };
+
+    ctx.ws_sender.send(ctx.reply(&response)?).await?;
+    Ok(())
+}
+
+async fn handle_resume(
+    ctx: &mut ConnectionContext,
+    token: String,
+    last_seq: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let claims = match ctx.auth.validate_token(&token, TokenKind::Access).await {
+        Ok(claims) => claims,
+        Err(e) => {
+            let response = ServerMessage::ResumeFailed {
+                reason: e.to_string(),
+            };
+            ctx.ws_sender.send(ctx.reply(&response)?).await?;
+            return Ok(());
+        }
+    };
+
+    let Some(resumed) = ctx
+        .sessions
+        .resume(&claims.sub, ctx.connection_id, last_seq)
+    else {
+        let response = ServerMessage::ResumeFailed {
+            reason: "No session to resume".to_string(),
+        };
+        ctx.ws_sender.send(ctx.reply(&response)?).await?;
+        return Ok(());
+    };
+
+    // Only a resume that found its session may take a slot of the account's connection
+    // limit, which can end the account's oldest session.
+    if !attach_account(ctx, vec![claims.clone()]).await {
+        ctx.sessions
+            .cancel_resume(&claims.sub, ctx.connection_id, resumed);
+        let response = ServerMessage::ResumeFailed {
+            reason: "Session limit reached".to_string(),
+        };
+        ctx.ws_sender.send(ctx.reply(&response)?).await?;
+        return Ok(());
+    }
+
+    if ctx.player_id != claims.sub {
+        leave_room(ctx).await;
+    }
+    ctx.player_id = claims.sub.clone();
+    ctx.registry.bind_player(ctx.connection_id, &ctx.player_id);
+    ctx.claims = Some(claims);
+    ctx.access_token = Some(token);
+    ctx.room_id = resumed.room_id.clone();
+    ctx.registry.set_room(ctx.connection_id, &ctx.room_id);
+
+    let response = ServerMessage::Resumed {
+        room_id: resumed.room_id,
+        last_seq: resumed.last_seq,
+        history_truncated: resumed.history_truncated,
+    };
+    ctx.ws_sender.send(ctx.reply(&response)?).await?;
+
+    for (seq, state) in resumed.missed_updates {
+        let update = ServerMessage::GameUpdate { seq, state };
+        ctx.ws_sender.send(ctx.push(&update)?).await?;
+    }
+
+    let update = json!({ "event": "player_reconnected", "player_id": ctx.player_id });
+    ctx.game_manager
+        .broadcast_to_room(&ctx.room_id, &update)
+        .await;
+    Ok(())
+}
+
+async fn handle_game_action(
+    ctx: &mut ConnectionContext,
+    action: String,
+    params: serde_json::Value,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !has_valid_session(ctx).await || ctx.room_id.is_empty() {
+        let response = ServerMessage::error(ErrorCode::NotInRoom, "Not seated in a room");
+        ctx.ws_sender.send(ctx.reply(&response)?).await?;
+        return Ok(());
+    }
+
+    let result = ctx
+        .game_manager
+        .handle_action(ctx.room_id.clone(), ctx.player_id.clone(), action, params)
+        .await;
+    match result {
+        Ok(event) => {
+            // The room pushes the event to the other players, the acting one gets it as
+            // the reply so the request id comes back with it
+            let state = serde_json::to_value(&event)?;
+            let seq = ctx.sessions.record_update(&ctx.player_id, &state);
+            let response = ServerMessage::GameUpdate { seq, state };
+            ctx.ws_sender.send(ctx.reply(&response)?).await?;
+        }
+        Err(e) => ctx.ws_sender.send(ctx.reply(&e.into())?).await?,
+    }
+    Ok(())
+}
+
+async fn handle_quit(ctx: &mut ConnectionContext) -> Result<(), Box<dyn std::error::Error>> {
+    let response = ServerMessage::Echo {
+        message: "Goodbye!".into(),
+    };
+    ctx.ws_sender.send(ctx.reply(&response)?).await?;
+    Ok(())
+}
+
+async fn handle_select_game(
+    ctx: &mut ConnectionContext,
+    game_type: String,
+    high_limit: bool,
+    free_play: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !has_valid_session(ctx).await {
+        let response = ServerMessage::error(ErrorCode::Unauthorized, "Not authenticated");
+        ctx.ws_sender.send(ctx.reply(&response)?).await?;
+        return Ok(());
+    }
+
+    let game_type = match playable_game_type(&game_type) {
+        Ok(game_type) => game_type,
+        Err(response) => {
+            ctx.ws_sender.send(ctx.reply(&response)?).await?;
+            return Ok(());
+        }
+    };
+
+    let guest = ctx.claims.as_ref().is_some_and(|claims| claims.guest);
+    let settings = RoomSettings {
+        high_limit,
+        free_play: free_play || guest,
+        ..RoomSettings::default()
+    };
+
+    // A player sits at one table at a time, so picking another game gives up the seat
+    leave_room(ctx).await;
+    let assigned = ctx
+        .game_manager
+        .assign_to_room(ctx.player_id.clone(), game_type.clone(), settings)
+        .await;
+    ctx.room_id = match assigned {
+        Ok(room_id) => room_id,
+        Err(e) => {
+            ctx.ws_sender.send(ctx.reply(&e.into())?).await?;
+            return Ok(());
+        }
+    };
+    ctx.sessions
+        .join(&ctx.player_id, &ctx.room_id, ctx.connection_id);
+    ctx.registry.set_room(ctx.connection_id, &ctx.room_id);
+
+    let response = ServerMessage::GameAssigned {
+        room_id: ctx.room_id.clone(),
+        game_type: game_type.to_db_string().to_string(),
+    };
+
+    ctx.ws_sender.send(ctx.reply(&response)?).await?;
+
+    Ok(())
+}
+
+async fn handle_create_private_room(
+    ctx: &mut ConnectionContext,
+    game_type: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let game_type = match playable_game_type(&game_type) {
+        Ok(game_type) => game_type,
+        Err(response) => {
+            ctx.ws_sender.send(ctx.reply(&response)?).await?;
+            return Ok(());
+        }
+    };
+
+    leave_room(ctx).await;
+    let created = ctx
+        .game_manager
+        .create_private_room(ctx.player_id.clone(), game_type.clone())
+        .await;
+    ctx.room_id = match created {
+        Ok(room_id) => room_id,
+        Err(e) => {
+            ctx.ws_sender.send(ctx.reply(&e.into())?).await?;
+            return Ok(());
+        }
+    };
+    ctx.sessions
+        .join(&ctx.player_id, &ctx.room_id, ctx.connection_id);
+    ctx.registry.set_room(ctx.connection_id, &ctx.room_id);
+
+    let response = ServerMessage::GameAssigned {
+        room_id: ctx.room_id.clone(),
+        game_type: game_type.to_db_string().to_string(),
+    };
+
+    ctx.ws_sender.send(ctx.reply(&response)?).await?;
+
+    Ok(())
+}
+
+/// Reads a game type that rooms can be opened for, or the error to answer with.
+fn playable_game_type(game_type: &str) -> Result<GameType, ServerMessage> {
+    match GameType::from_db_string(game_type) {
+        Some(parsed) if GameManager::playable_game_types().contains(&parsed) => Ok(parsed),
+        parsed => Err(ServerMessage::Error {
+            code: ErrorCode::InvalidGameType,
+            message: match parsed {
+                Some(_) => "Game type is not playable",
+                None => "Invalid game type",
+            }
+            .to_string(),
+            details: Some(json!({ "game_type": game_type })),
+        }),
+    }
+}
+
+/// Stores a freshly issued token pair on the connection and builds the matching
+/// `AuthSuccess` response.
+async fn start_session(ctx: &mut ConnectionContext, tokens: TokenPair) -> ServerMessage {
+    if !attach_account(ctx, vec![tokens.claims.clone(), tokens.refresh_claims]).await {
+        return ServerMessage::SessionLimitReached {
+            max_sessions: ctx.registry.max_per_account(),
+        };
+    }
+
+    if ctx.player_id != tokens.claims.sub {
+        leave_room(ctx).await;
+    }
+    ctx.player_id = tokens.claims.sub.clone();
+    ctx.registry.bind_player(ctx.connection_id, &ctx.player_id);
+    ctx.claims = Some(tokens.claims);
+    ctx.access_token = Some(tokens.access_token.clone());
+
+    ServerMessage::AuthSuccess {
+        token: tokens.access_token,
+        refresh_token: tokens.refresh_token,
+        expires_in: tokens.expires_in,
+    }
+}
+
+async fn start_guest_session(ctx: &mut ConnectionContext, guest: GuestSession) -> ServerMessage {
+    leave_room(ctx).await;
+    ctx.player_id = guest.claims.sub.clone();
+    ctx.registry.bind_player(ctx.connection_id, &ctx.player_id);
+    ctx.access_token = Some(guest.access_token.clone());
+
+    let response = ServerMessage::GuestSession {
+        token: guest.access_token,
+        player_id: guest.claims.sub.clone(),
+        display_name: guest.claims.username.clone(),
+        expires_in: guest.expires_in,
+        balance: guest.balance,
+    };
+    ctx.claims = Some(guest.claims);
+    response
+}
+
+/// Takes the connection's player out of their room for good, e.g. on logout or before the
+/// socket continues as another player, so the room stops taking the old player's actions.
+async fn leave_room(ctx: &mut ConnectionContext) {
+    if ctx.room_id.is_empty() {
+        return;
+    }
+
+    let room_id = std::mem::take(&mut ctx.room_id);
+    ctx.sessions.leave(&ctx.player_id);
+    ctx.registry.clear_room(ctx.connection_id);
+    ctx.game_manager
+        .remove_player(room_id, ctx.player_id.clone(), DisconnectReason::Left)
+        .await;
+}
+
+/// Registers the connection's account with the registry and revokes the tokens of any
+/// sessions ended to stay within the account's connection limit. Returns `false` if the
+/// session is refused. Guests have no account and are not limited.
+async fn attach_account(ctx: &ConnectionContext, tokens: Vec<Claims>) -> bool {
+    if tokens.first().is_some_and(|claims| claims.guest) {
+        return true;
+    }
+
+    let Some(Ok(account_id)) = tokens.first().map(Claims::account_id) else {
+        return false;
+    };
+
+    match ctx
+        .registry
+        .attach_account(ctx.connection_id, account_id, tokens)
+    {
+        Some(ended) => {
+            for session in ended {
+                revoke_session_tokens(&ctx.auth, session).await;
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+/// Closes a session and revokes its tokens so the device has to log in again. Returns
+/// `false` if there is no such session.
+async fn end_session(ctx: &ConnectionContext, session_id: Uuid, reason: &str) -> bool {
+    match ctx.registry.end_session(session_id, reason) {
+        Some(session) => {
+            revoke_session_tokens(&ctx.auth, session).await;
+            true
+        }
+        None => false,
+    }
+}
+
+async fn revoke_session_tokens(auth: &Arc<AuthService>, session: EndedSession) {
+    for claims in &session.tokens {
+        if let Err(e) = auth.revoke_token(claims, "session_ended").await {
+            eprintln!("Error revoking token of {}: {}", claims.username, e);
+        }
+    }
+}
+
+/// Re-validates the connection's access token so that expiry and revocation (logout
+/// elsewhere, bans) take effect immediately. Clears the session if it is no longer valid.
+async fn has_valid_session(ctx: &mut ConnectionContext) -> bool {
+    let valid = match &ctx.access_token {
+        Some(token) => ctx
+            .auth
+            .validate_token(token, TokenKind::Access)
+            .await
+            .is_ok(),
+        None => false,
+    };
+
+    if !valid {
+        ctx.claims = None;
+        ctx.access_token = None;
+        ctx.registry.detach_account(ctx.connection_id);
+    }
+    valid
+}
+
+/// Tells the client its message could not be decoded. Decoder errors can quote the values
+/// they choked on, so they are only logged and the client gets where decoding failed.
+async fn handle_parse_error(
+    ctx: &ConnectionContext,
+    parse_error: MessageError,
+) -> Result<(), Box<dyn std::error::Error>> {
+    eprintln!("Failed to parse message from {}: {}", ctx.addr, parse_error);
+
+    let details = match &parse_error {
+        MessageError::Json(e) => json!({
+            "category": format!("{:?}", e.classify()).to_lowercase(),
+            "line": e.line(),
+            "column": e.column(),
+        }),
+        _ => json!({ "category": "data" }),
+    };
+    let error_response = ServerMessage::Error {
+        code: ErrorCode::InvalidMessage,
+        message: "Failed to parse message".to_string(),
+        details: Some(details),
+    };
+    ctx.ws_sender.send(ctx.reply(&error_response)?).await?;
+    Ok(())
+}
+
+pub async fn start_server(
+    config: config::Config,
+    db_pool: Arc<DbPool>,
+    db_queue: Arc<DbQueue>,
+    auth: AuthService,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let addr = format!("{}:{}", config.server_address, config.server_port);
+    let tls = tls::from_config(&config)?;
+    let listener = TcpListener::bind(&addr).await?;
+    let scheme = if tls.is_some() { "wss" } else { "ws" };
+    println!("Server started and listening on {}://{}", scheme, addr);
+
+    let session_limit_policy: SessionLimitPolicy = config.session_limit_policy.parse()?;
+    let sessions = Arc::new(SessionStore::new());
+    let registry = Arc::new(ConnectionRegistry::new(
+        Arc::clone(&sessions),
+        config.max_sessions_per_account,
+        session_limit_policy,
+    ));
+    let game_manager = GameManager::new(db_pool.clone(), db_queue.clone(), registry.clone());
+
+    let state = Arc::new(ServerState {
+        game_manager: Arc::new(game_manager),
+        auth: Arc::new(auth),
+        sessions,
+        registry,
+        resume_grace: Duration::from_secs(config.resume_grace_secs),
+        ping_interval: Duration::from_secs(config.ping_interval_secs),
+        idle_timeout: Duration::from_secs(config.idle_timeout_secs),
+        max_message_bytes: config.max_message_bytes,
+        compression: config.compression_enabled.then_some(Compression {
+            threshold: config.compression_threshold_bytes,
+        }),
+        rate_limits: RateLimits::from_config(&config)?,
+        server_password: config.server_password,
+        tls,
+        db_pool,
+        db_queue,
+        metrics: Metrics::default(),
+        shutting_down: AtomicBool::new(false),
+        admission: Arc::new(Admission::new(
+            config.max_connections,
+            config.max_connections_per_ip,
+        )),
+        refusal_slots: Arc::new(Semaphore::new(MAX_PENDING_REFUSALS)),
+    });
+
+    if let Some(port) = config.http_port {
+        let http_addr = format!("{}:{}", config.server_address, port);
+        let http_listener = TcpListener::bind(&http_addr).await?;
+        println!("HTTP endpoints listening on http://{}", http_addr);
+        tokio::spawn(http::serve(http_listener, Arc::clone(&state)));
+    }
+
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, addr) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        eprintln!("Error accepting connection: {}", e);
+                        continue;
+                    }
+                };
+                let state = Arc::clone(&state);
+
+                let admitted = state.admission.try_admit(addr.ip());
+                match &admitted {
+                    Ok(_) => state.metrics.connection_accepted(),
+                    Err(refusal) => {
+                        state.metrics.connection_refused();
+                        println!("Refused connection from {}: {}", addr, refusal);
+                    }
+                }
+
+                if admitted.is_ok() {
+                    connections.spawn(async move {
+                        if let Err(e) = accept_connection(stream, addr, state, admitted).await {
+                            eprintln!("Error handling connection: {:?}", e);
+                        }
+                    });
+                } else if let Ok(slot) = Arc::clone(&state.refusal_slots).try_acquire_owned() {
+                    connections.spawn(async move {
+                        let refused = accept_connection(stream, addr, state, admitted);
+                        match tokio::time::timeout(REFUSAL_TIMEOUT, refused).await {
+                            Ok(Err(e)) => eprintln!("Error refusing connection: {:?}", e),
+                            Err(_) => eprintln!("Refused connection from {} timed out", addr),
+                            Ok(Ok(())) => {}
+                        }
+                        drop(slot);
+                    });
+                }
+            }
+            // Reap finished connections so the set does not grow for the server's lifetime
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+            _ = shutdown_signal() => break,
+        }
+    }
+
+    drop(listener);
+    shutdown(
+        &state,
+        connections,
+        Duration::from_secs(config.shutdown_timeout_secs),
+    )
+    .await;
+    Ok(())
+}
+
+/// Resolves on SIGINT, or SIGTERM on unix.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            eprintln!("Error listening for ctrl-c: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                eprintln!("Error listening for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Tells every client that the server is going away, gives running rounds up to `timeout`
+/// to finish and voids the rest, then closes the sockets and waits for their tasks to wind
+/// down within the same deadline.
+async fn shutdown(state: &ServerState, mut connections: JoinSet<()>, timeout: Duration) {
+    println!("Shutting down, no longer accepting connections");
+    state.shutting_down.store(true, Ordering::Relaxed);
+    let deadline = tokio::time::Instant::now() + timeout;
+
+    state.registry.send_to_all(&ServerMessage::ServerShutdown {
+        timeout_secs: timeout.as_secs(),
+    });
+
+    loop {
+        let running = state.game_manager.rounds_in_progress().await;
+        if running == 0 || tokio::time::Instant::now() >= deadline {
+            break;
+        }
+        tokio::time::sleep(ROUND_POLL_INTERVAL).await;
+    }
+    let voided = state.game_manager.void_rounds().await;
+    if voided > 0 {
+        println!("Voided {} rounds that did not finish in time", voided);
+    }
+
+    let closed = state.registry.close_all("Server shutting down");
+    println!("Closing {} connections", closed);
+    let drained = tokio::time::timeout_at(deadline, async {
+        while connections.join_next().await.is_some() {}
+    })
+    .await;
+    if drained.is_err() {
+        println!(
+            "Aborting {} connections that did not close in time",
+            connections.len()
+        );
+        connections.shutdown().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_the_supported_protocol_versions() {
+        for version in MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION {
+            assert_eq!(unsupported_version(version), None);
+        }
+        assert_eq!(
+            unsupported_version(PROTOCOL_VERSION + 1).as_deref(),
+            Some(format!("Protocol version {} is not supported", PROTOCOL_VERSION + 1).as_str())
+        );
+        assert!(unsupported_version(MIN_PROTOCOL_VERSION - 1).is_some());
+    }
+
+    #[test]
+    fn hello_defaults_its_optional_fields() {
+        let envelope =
+            parse_client_message(r#"{"type":"Hello","data":{"protocol_version":1}}"#).unwrap();
+        match envelope.message {
+            ClientMessage::Hello {
+                protocol_version,
+                client_name,
+                capabilities,
+            } => {
+                assert_eq!(protocol_version, 1);
+                assert_eq!(client_name, None);
+                assert!(capabilities.is_empty());
+            }
+            other => panic!("expected a Hello, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn welcome_announces_deflate_only_when_compression_is_enabled() {
+        let features = |compression| match welcome(compression) {
+            ServerMessage::Welcome {
+                protocol_version,
+                game_types,
+                features,
+                ..
+            } => {
+                assert_eq!(protocol_version, PROTOCOL_VERSION);
+                assert!(game_types.contains(&"EUROPEAN_ROULETTE".to_string()));
+                features
+            }
+            other => panic!("expected a Welcome, got {:?}", other),
+        };
+
+        assert!(!features(None).contains(&"deflate".to_string()));
+        assert!(features(Some(Compression { threshold: 1024 })).contains(&"deflate".to_string()));
+    }
+}
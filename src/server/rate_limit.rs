@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+
+/// Sustained rate and burst size of a token bucket.
+#[derive(Debug, Clone, Copy)]
+pub struct Rate {
+    per_sec: f64,
+    burst: f64,
+}
+
+impl FromStr for Rate {
+    type Err = String;
+
+    /// Parses `<messages per second>/<burst>`, e.g. `0.5/3`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid rate limit: {}", s);
+        let (per_sec, burst) = s.split_once('/').ok_or_else(invalid)?;
+        let per_sec: f64 = per_sec.trim().parse().map_err(|_| invalid())?;
+        let burst: f64 = burst.trim().parse().map_err(|_| invalid())?;
+        if per_sec <= 0.0 || burst < 1.0 {
+            return Err(invalid());
+        }
+        Ok(Rate { per_sec, burst })
+    }
+}
+
+/// Limits shared by every connection, read from the `[limits]` and `[message_limits]`
+/// sections.
+pub struct RateLimits {
+    connection: Rate,
+    by_type: HashMap<String, Rate>,
+    max_violations: u32,
+    violation_window: Duration,
+}
+
+impl RateLimits {
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let connection = Rate {
+            per_sec: config.messages_per_sec,
+            burst: f64::from(config.message_burst),
+        };
+        if connection.per_sec <= 0.0 || connection.burst < 1.0 {
+            return Err("messages_per_sec and burst must be positive".to_string());
+        }
+
+        let by_type = config
+            .message_type_limits
+            .iter()
+            .map(|(kind, rate)| Ok((kind.clone(), rate.parse()?)))
+            .collect::<Result<_, String>>()?;
+
+        Ok(RateLimits {
+            connection,
+            by_type,
+            max_violations: config.max_rate_violations,
+            violation_window: Duration::from_secs(config.rate_violation_window_secs),
+        })
+    }
+}
+
+struct TokenBucket {
+    rate: Rate,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: Rate, now: Instant) -> Self {
+        TokenBucket {
+            rate,
+            tokens: rate.burst,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate.per_sec).min(self.rate.burst);
+        self.updated = now;
+    }
+
+    /// Time until the bucket holds a whole token again, zero if it already does.
+    fn wait_time(&self) -> Duration {
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / self.rate.per_sec)
+        }
+    }
+}
+
+/// Outcome of checking a message against the limits of its connection.
+pub enum RateDecision {
+    Allowed,
+    /// The message is dropped; the client may retry after the given time.
+    Limited(Duration),
+    /// The client kept going over its limits and has to be disconnected.
+    Disconnect,
+}
+
+/// Token buckets of one connection: one for all of its messages and one per limited message
+/// type. A message only takes tokens if every bucket it counts against has one, so a message
+/// dropped by its type limit does not use up the connection's budget.
+pub struct RateLimiter<'a> {
+    limits: &'a RateLimits,
+    connection: TokenBucket,
+    by_type: HashMap<&'static str, TokenBucket>,
+    violations: u32,
+    window_started: Instant,
+}
+
+impl<'a> RateLimiter<'a> {
+    pub fn new(limits: &'a RateLimits) -> Self {
+        let now = Instant::now();
+        RateLimiter {
+            limits,
+            connection: TokenBucket::new(limits.connection, now),
+            by_type: HashMap::new(),
+            violations: 0,
+            window_started: now,
+        }
+    }
+
+    pub fn check(&mut self, kind: &'static str) -> RateDecision {
+        let now = Instant::now();
+        self.connection.refill(now);
+
+        let mut wait = self.connection.wait_time();
+        let mut type_bucket = match self.limits.by_type.get(kind) {
+            Some(rate) => {
+                let bucket = self
+                    .by_type
+                    .entry(kind)
+                    .or_insert_with(|| TokenBucket::new(*rate, now));
+                bucket.refill(now);
+                wait = wait.max(bucket.wait_time());
+                Some(bucket)
+            }
+            None => None,
+        };
+
+        if wait.is_zero() {
+            self.connection.tokens -= 1.0;
+            if let Some(bucket) = type_bucket.as_mut() {
+                bucket.tokens -= 1.0;
+            }
+            return RateDecision::Allowed;
+        }
+
+        if now.duration_since(self.window_started) > self.limits.violation_window {
+            self.violations = 0;
+            self.window_started = now;
+        }
+        self.violations += 1;
+        if self.violations > self.limits.max_violations {
+            RateDecision::Disconnect
+        } else {
+            RateDecision::Limited(wait)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(connection: &str, by_type: &[(&str, &str)], max_violations: u32) -> RateLimits {
+        RateLimits {
+            connection: connection.parse().unwrap(),
+            by_type: by_type
+                .iter()
+                .map(|(kind, rate)| (kind.to_string(), rate.parse().unwrap()))
+                .collect(),
+            max_violations,
+            violation_window: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn parses_rates() {
+        let rate: Rate = "0.5/3".parse().unwrap();
+        assert_eq!(rate.per_sec, 0.5);
+        assert_eq!(rate.burst, 3.0);
+
+        let rate: Rate = " 20 / 40 ".parse().unwrap();
+        assert_eq!(rate.per_sec, 20.0);
+        assert_eq!(rate.burst, 40.0);
+    }
+
+    #[test]
+    fn rejects_invalid_rates() {
+        for rate in ["", "5", "a/3", "5/b", "0/3", "-1/3", "5/0.5"] {
+            assert!(rate.parse::<Rate>().is_err(), "{:?} parsed", rate);
+        }
+    }
+
+    #[test]
+    fn allows_a_burst_then_limits() {
+        let limits = limits("1/3", &[], 10);
+        let mut limiter = RateLimiter::new(&limits);
+
+        for _ in 0..3 {
+            assert!(matches!(limiter.check("echo"), RateDecision::Allowed));
+        }
+        match limiter.check("echo") {
+            RateDecision::Limited(wait) => assert!(wait > Duration::from_millis(900)),
+            _ => panic!("fourth message was not limited"),
+        }
+    }
+
+    #[test]
+    fn limits_message_types_without_using_the_connection_budget() {
+        let limits = limits("1/3", &[("hello", "1/1")], 10);
+        let mut limiter = RateLimiter::new(&limits);
+
+        assert!(matches!(limiter.check("hello"), RateDecision::Allowed));
+        assert!(matches!(limiter.check("hello"), RateDecision::Limited(_)));
+        assert!(matches!(limiter.check("echo"), RateDecision::Allowed));
+        assert!(matches!(limiter.check("echo"), RateDecision::Allowed));
+    }
+
+    #[test]
+    fn disconnects_after_too_many_violations() {
+        let limits = limits("1/1", &[], 2);
+        let mut limiter = RateLimiter::new(&limits);
+
+        assert!(matches!(limiter.check("echo"), RateDecision::Allowed));
+        assert!(matches!(limiter.check("echo"), RateDecision::Limited(_)));
+        assert!(matches!(limiter.check("echo"), RateDecision::Limited(_)));
+        assert!(matches!(limiter.check("echo"), RateDecision::Disconnect));
+    }
+}
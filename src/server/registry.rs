@@ -0,0 +1,603 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite::protocol::Message;
+use ts_rs::TS;
+use uuid::Uuid;
+
+use super::session::SessionStore;
+use crate::auth::Claims;
+use crate::game::Outbox;
+use crate::message::{encode_server_message, Encoding, ServerEnvelope, ServerMessage};
+
+/// What happens when an account opens more connections than it is allowed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionLimitPolicy {
+    /// The new session is refused.
+    Reject,
+    /// The oldest sessions of the account are ended to make room for the new one.
+    EndOldest,
+}
+
+impl FromStr for SessionLimitPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(SessionLimitPolicy::Reject),
+            "end_oldest" => Ok(SessionLimitPolicy::EndOldest),
+            other => Err(format!("Unknown session limit policy: {}", other)),
+        }
+    }
+}
+
+/// A socket of an account, as shown to the player and to admins.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, TS)]
+pub struct SessionInfo {
+    pub session_id: Uuid,
+    pub device: Option<String>,
+    pub ip: IpAddr,
+    pub connected_since: DateTime<Utc>,
+    pub room_id: Option<String>,
+}
+
+/// A session that was ended, with the tokens it was using so that they can be revoked.
+pub struct EndedSession {
+    pub tokens: Vec<Claims>,
+}
+
+struct Connection {
+    account_id: Option<i32>,
+    device: Option<String>,
+    ip: IpAddr,
+    connected_since: DateTime<Utc>,
+    authenticated_at: Option<DateTime<Utc>>,
+    room_id: Option<String>,
+    tokens: Vec<Claims>,
+    encoding: Encoding,
+    pushes: PushSequence,
+    /// Queue of the socket's writer task.
+    sender: mpsc::Sender<Message>,
+    /// Tells the connection's task to close the socket, with the reason sent to the client.
+    close: Option<oneshot::Sender<String>>,
+}
+
+/// Numbers the messages the server pushes on a connection without being asked, shared by the
+/// connection's own task and the registry.
+#[derive(Debug, Clone, Default)]
+pub struct PushSequence(Arc<AtomicU64>);
+
+impl PushSequence {
+    pub fn next(&self) -> u64 {
+        self.0.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
+impl Connection {
+    fn info(&self, session_id: Uuid) -> SessionInfo {
+        SessionInfo {
+            session_id,
+            device: self.device.clone(),
+            ip: self.ip,
+            connected_since: self.connected_since,
+            room_id: self.room_id.clone(),
+        }
+    }
+
+    fn end(&mut self, reason: &str) -> EndedSession {
+        if let Some(close) = self.close.take() {
+            let _ = close.send(reason.to_string());
+        }
+        EndedSession {
+            tokens: std::mem::take(&mut self.tokens),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Connections {
+    by_id: HashMap<Uuid, Connection>,
+    /// Connections a player is logged in on, e.g. from several devices.
+    by_player: HashMap<String, HashSet<Uuid>>,
+}
+
+impl Connections {
+    fn remove(&mut self, connection_id: Uuid) -> Option<Connection> {
+        self.unbind(connection_id);
+        self.by_id.remove(&connection_id)
+    }
+
+    fn unbind(&mut self, connection_id: Uuid) {
+        self.by_player.retain(|_, ids| {
+            ids.remove(&connection_id);
+            !ids.is_empty()
+        });
+    }
+
+    /// Connections that get a player's updates from a room: the ones seated in that room,
+    /// or, while none is, the ones not seated anywhere, like a socket whose seat is being
+    /// set up.
+    fn seated_in(&self, room_id: &str, player_id: &str) -> Vec<&Connection> {
+        let connections: Vec<&Connection> = self
+            .by_player
+            .get(player_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| self.by_id.get(id))
+            .collect();
+        let seated: Vec<&Connection> = connections
+            .iter()
+            .copied()
+            .filter(|c| c.room_id.as_deref() == Some(room_id))
+            .collect();
+        if !seated.is_empty() {
+            return seated;
+        }
+        connections
+            .into_iter()
+            .filter(|c| c.room_id.is_none())
+            .collect()
+    }
+}
+
+/// Every open socket of the server, so that the sessions of an account can be listed, a
+/// particular one closed from another connection, and game updates pushed to players.
+pub struct ConnectionRegistry {
+    connections: Mutex<Connections>,
+    sessions: Arc<SessionStore>,
+    max_per_account: usize,
+    on_limit: SessionLimitPolicy,
+}
+
+impl ConnectionRegistry {
+    pub fn new(
+        sessions: Arc<SessionStore>,
+        max_per_account: usize,
+        on_limit: SessionLimitPolicy,
+    ) -> Self {
+        ConnectionRegistry {
+            connections: Mutex::new(Connections::default()),
+            sessions,
+            max_per_account,
+            on_limit,
+        }
+    }
+
+    pub fn max_per_account(&self) -> usize {
+        self.max_per_account
+    }
+
+    /// Registers a new socket, the encoding it negotiated, the numbering of its pushes and the
+    /// queue of its writer task. The returned receiver yields the reason once the session is
+    /// ended from elsewhere.
+    pub fn register(
+        &self,
+        connection_id: Uuid,
+        ip: IpAddr,
+        device: Option<String>,
+        encoding: Encoding,
+        pushes: PushSequence,
+        sender: mpsc::Sender<Message>,
+    ) -> oneshot::Receiver<String> {
+        let (close, closed) = oneshot::channel();
+        self.connections.lock().unwrap().by_id.insert(
+            connection_id,
+            Connection {
+                account_id: None,
+                device,
+                ip,
+                connected_since: Utc::now(),
+                authenticated_at: None,
+                room_id: None,
+                tokens: Vec::new(),
+                encoding,
+                pushes,
+                sender,
+                close: Some(close),
+            },
+        );
+        closed
+    }
+
+    pub fn remove(&self, connection_id: Uuid) {
+        self.connections.lock().unwrap().remove(connection_id);
+    }
+
+    /// Routes the game updates of a player to this connection, e.g. after a login or resume.
+    pub fn bind_player(&self, connection_id: Uuid, player_id: &str) {
+        let mut connections = self.connections.lock().unwrap();
+        if connections.by_id.contains_key(&connection_id) {
+            connections.unbind(connection_id);
+            connections
+                .by_player
+                .entry(player_id.to_string())
+                .or_default()
+                .insert(connection_id);
+        }
+    }
+
+    /// Associates a socket with an account and the tokens it authenticated with, applying
+    /// the per-account connection limit.
+    ///
+    /// Returns `None` if the session is refused, otherwise the sessions that were ended to
+    /// make room for it.
+    pub fn attach_account(
+        &self,
+        connection_id: Uuid,
+        account_id: i32,
+        tokens: Vec<Claims>,
+    ) -> Option<Vec<EndedSession>> {
+        let mut connections = self.connections.lock().unwrap();
+
+        let mut others: Vec<(Uuid, DateTime<Utc>)> = connections
+            .by_id
+            .iter()
+            .filter(|(id, c)| **id != connection_id && c.account_id == Some(account_id))
+            .map(|(id, c)| (*id, c.authenticated_at.unwrap_or(c.connected_since)))
+            .collect();
+
+        let mut ended = Vec::new();
+        if others.len() >= self.max_per_account {
+            match self.on_limit {
+                SessionLimitPolicy::Reject => return None,
+                SessionLimitPolicy::EndOldest => {
+                    others.sort_by_key(|(_, since)| *since);
+                    let excess = others.len() + 1 - self.max_per_account.max(1);
+                    for (id, _) in others.into_iter().take(excess) {
+                        if let Some(mut connection) = connections.remove(id) {
+                            ended.push(connection.end("Session limit reached"));
+                        }
+                    }
+                }
+            }
+        }
+
+        let connection = connections.by_id.get_mut(&connection_id)?;
+        if connection.account_id != Some(account_id) {
+            connection.tokens.clear();
+            connection.authenticated_at = Some(Utc::now());
+        }
+        connection.account_id = Some(account_id);
+        connection.tokens.extend(tokens);
+        Some(ended)
+    }
+
+    /// Forgets the account and player of a socket, e.g. on logout.
+    pub fn detach_account(&self, connection_id: Uuid) {
+        let mut connections = self.connections.lock().unwrap();
+        connections.unbind(connection_id);
+        if let Some(connection) = connections.by_id.get_mut(&connection_id) {
+            connection.account_id = None;
+            connection.authenticated_at = None;
+            connection.room_id = None;
+            connection.tokens.clear();
+        }
+    }
+
+    pub fn set_room(&self, connection_id: Uuid, room_id: &str) {
+        if let Some(connection) = self
+            .connections
+            .lock()
+            .unwrap()
+            .by_id
+            .get_mut(&connection_id)
+        {
+            connection.room_id = Some(room_id.to_string());
+        }
+    }
+
+    pub fn clear_room(&self, connection_id: Uuid) {
+        if let Some(connection) = self
+            .connections
+            .lock()
+            .unwrap()
+            .by_id
+            .get_mut(&connection_id)
+        {
+            connection.room_id = None;
+        }
+    }
+
+    pub fn account_sessions(&self, account_id: i32) -> Vec<SessionInfo> {
+        let connections = self.connections.lock().unwrap();
+        let mut sessions: Vec<SessionInfo> = connections
+            .by_id
+            .iter()
+            .filter(|(_, c)| c.account_id == Some(account_id))
+            .map(|(id, c)| c.info(*id))
+            .collect();
+        sessions.sort_by_key(|session| session.connected_since);
+        sessions
+    }
+
+    /// Number of open sockets and how many of them are logged in to an account.
+    pub fn connection_counts(&self) -> (usize, usize) {
+        let connections = self.connections.lock().unwrap();
+        let authenticated = connections
+            .by_id
+            .values()
+            .filter(|c| c.account_id.is_some())
+            .count();
+        (connections.by_id.len(), authenticated)
+    }
+
+    pub fn account_of(&self, connection_id: Uuid) -> Option<i32> {
+        self.connections
+            .lock()
+            .unwrap()
+            .by_id
+            .get(&connection_id)
+            .and_then(|c| c.account_id)
+    }
+
+    /// Queues a message on every open socket, authenticated or not.
+    pub fn send_to_all(&self, message: &ServerMessage) {
+        for connection in self.connections.lock().unwrap().by_id.values() {
+            let envelope = ServerEnvelope::push(message, connection.pushes.next());
+            match encode_server_message(&envelope, connection.encoding) {
+                Ok(frame) => {
+                    let _ = connection.sender.try_send(frame);
+                }
+                Err(e) => eprintln!("Error encoding server message: {}", e),
+            }
+        }
+    }
+
+    /// Closes every socket, e.g. on shutdown. Tokens stay valid so that clients can log
+    /// back in once the server is up again.
+    pub fn close_all(&self, reason: &str) -> usize {
+        let mut connections = self.connections.lock().unwrap();
+        connections.by_player.clear();
+        let closed = connections.by_id.len();
+        for (_, mut connection) in connections.by_id.drain() {
+            connection.end(reason);
+        }
+        closed
+    }
+
+    /// Closes every socket of an account but `except`, e.g. once all of its tokens were
+    /// revoked. Returns how many were closed.
+    pub fn end_account_sessions(
+        &self,
+        account_id: i32,
+        except: Option<Uuid>,
+        reason: &str,
+    ) -> usize {
+        let mut connections = self.connections.lock().unwrap();
+        let ids: Vec<Uuid> = connections
+            .by_id
+            .iter()
+            .filter(|(id, c)| Some(**id) != except && c.account_id == Some(account_id))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &ids {
+            if let Some(mut connection) = connections.remove(*id) {
+                connection.end(reason);
+            }
+        }
+        ids.len()
+    }
+
+    /// Closes a socket. The entry is removed right away so the session no longer counts
+    /// against the account's limit.
+    pub fn end_session(&self, connection_id: Uuid, reason: &str) -> Option<EndedSession> {
+        self.connections
+            .lock()
+            .unwrap()
+            .remove(connection_id)
+            .map(|mut connection| connection.end(reason))
+    }
+}
+
+impl Outbox for ConnectionRegistry {
+    /// Records the update for replay and queues it on the player's sockets seated in the
+    /// room. Updates of players without a room session are sent with sequence number 0 and
+    /// are not replayed.
+    fn send_to_player(&self, room_id: &str, player_id: &str, update: &serde_json::Value) {
+        let seq = self.sessions.record_update(player_id, update);
+        let message = ServerMessage::GameUpdate {
+            seq,
+            state: update.clone(),
+        };
+
+        let connections = self.connections.lock().unwrap();
+        for connection in connections.seated_in(room_id, player_id) {
+            let envelope = ServerEnvelope::push(&message, connection.pushes.next());
+            let frame = match encode_server_message(&envelope, connection.encoding) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    eprintln!("Error encoding game update: {}", e);
+                    continue;
+                }
+            };
+            if let Err(e) = connection.sender.try_send(frame) {
+                eprintln!("Dropping game update for {}: {}", player_id, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::TokenKind;
+
+    fn registry(max_per_account: usize, on_limit: SessionLimitPolicy) -> ConnectionRegistry {
+        ConnectionRegistry::new(Arc::new(SessionStore::new()), max_per_account, on_limit)
+    }
+
+    fn connect(registry: &ConnectionRegistry) -> (Uuid, oneshot::Receiver<String>) {
+        let connection_id = Uuid::new_v4();
+        let (sender, _) = mpsc::channel(1);
+        let closed = registry.register(
+            connection_id,
+            IpAddr::from([127, 0, 0, 1]),
+            None,
+            Encoding::default(),
+            PushSequence::default(),
+            sender,
+        );
+        (connection_id, closed)
+    }
+
+    /// Connects a socket for the player and returns the queue of its writer task.
+    fn connect_player(
+        registry: &ConnectionRegistry,
+        player_id: &str,
+    ) -> (Uuid, mpsc::Receiver<Message>) {
+        let connection_id = Uuid::new_v4();
+        let (sender, queue) = mpsc::channel(8);
+        registry.register(
+            connection_id,
+            IpAddr::from([127, 0, 0, 1]),
+            None,
+            Encoding::default(),
+            PushSequence::default(),
+            sender,
+        );
+        registry.bind_player(connection_id, player_id);
+        (connection_id, queue)
+    }
+
+    fn received(queue: &mut mpsc::Receiver<Message>) -> usize {
+        let mut count = 0;
+        while queue.try_recv().is_ok() {
+            count += 1;
+        }
+        count
+    }
+
+    fn tokens(account_id: i32) -> Vec<Claims> {
+        vec![Claims {
+            sub: account_id.to_string(),
+            username: "alice".to_string(),
+            iss: "ro-rust".to_string(),
+            iat: 0,
+            exp: 0,
+            jti: Uuid::new_v4().to_string(),
+            typ: TokenKind::Access,
+            roles: Vec::new(),
+            guest: false,
+        }]
+    }
+
+    fn session_ids(registry: &ConnectionRegistry, account_id: i32) -> Vec<Uuid> {
+        registry
+            .account_sessions(account_id)
+            .iter()
+            .map(|session| session.session_id)
+            .collect()
+    }
+
+    #[test]
+    fn reject_policy_refuses_sessions_over_the_limit() {
+        let registry = registry(2, SessionLimitPolicy::Reject);
+        let (first, _) = connect(&registry);
+        let (second, _) = connect(&registry);
+        let (third, _) = connect(&registry);
+
+        assert!(registry.attach_account(first, 7, tokens(7)).is_some());
+        assert!(registry.attach_account(second, 7, tokens(7)).is_some());
+        assert!(registry.attach_account(third, 7, tokens(7)).is_none());
+        assert_eq!(registry.account_of(third), None);
+        assert_eq!(registry.account_sessions(7).len(), 2);
+    }
+
+    #[test]
+    fn end_oldest_policy_ends_the_oldest_session() {
+        let registry = registry(2, SessionLimitPolicy::EndOldest);
+        let (first, mut first_closed) = connect(&registry);
+        let (second, _) = connect(&registry);
+        let (third, _) = connect(&registry);
+        let first_tokens = tokens(7);
+        let first_jti = first_tokens[0].jti.clone();
+
+        registry.attach_account(first, 7, first_tokens).unwrap();
+        registry.attach_account(second, 7, tokens(7)).unwrap();
+        let ended = registry.attach_account(third, 7, tokens(7)).unwrap();
+
+        assert_eq!(ended.len(), 1);
+        assert_eq!(ended[0].tokens[0].jti, first_jti);
+        assert_eq!(first_closed.try_recv().unwrap(), "Session limit reached");
+        assert_eq!(session_ids(&registry, 7), vec![second, third]);
+    }
+
+    #[test]
+    fn limits_apply_per_account() {
+        let registry = registry(1, SessionLimitPolicy::Reject);
+        let (first, _) = connect(&registry);
+        let (second, _) = connect(&registry);
+
+        assert!(registry.attach_account(first, 7, tokens(7)).is_some());
+        assert!(registry.attach_account(second, 8, tokens(8)).is_some());
+        // Authenticating again on the same socket does not take another place.
+        assert!(registry.attach_account(first, 7, tokens(7)).is_some());
+        assert_eq!(registry.connection_counts(), (2, 2));
+    }
+
+    #[test]
+    fn ending_the_sessions_of_an_account_spares_the_given_one() {
+        let registry = registry(3, SessionLimitPolicy::Reject);
+        let (first, mut first_closed) = connect(&registry);
+        let (second, mut second_closed) = connect(&registry);
+        registry.attach_account(first, 7, tokens(7)).unwrap();
+        registry.attach_account(second, 7, tokens(7)).unwrap();
+
+        assert_eq!(
+            registry.end_account_sessions(7, Some(second), "Password reset"),
+            1
+        );
+        assert_eq!(first_closed.try_recv().unwrap(), "Password reset");
+        assert!(second_closed.try_recv().is_err());
+        assert_eq!(session_ids(&registry, 7), vec![second]);
+    }
+
+    #[test]
+    fn detaching_frees_the_place_of_the_account() {
+        let registry = registry(1, SessionLimitPolicy::Reject);
+        let (first, _) = connect(&registry);
+        let (second, _) = connect(&registry);
+        registry.attach_account(first, 7, tokens(7)).unwrap();
+
+        registry.detach_account(first);
+        assert!(registry.attach_account(second, 7, tokens(7)).is_some());
+        assert_eq!(registry.connection_counts(), (2, 1));
+    }
+
+    #[test]
+    fn sends_game_updates_to_the_sockets_seated_in_the_room() {
+        let registry = registry(3, SessionLimitPolicy::Reject);
+        let (table, mut table_queue) = connect_player(&registry, "alice");
+        let (_, mut phone_queue) = connect_player(&registry, "alice");
+        let update = serde_json::json!({ "event": "spin" });
+
+        // Before any socket is seated, e.g. while a seat is being set up, all of them get it
+        registry.send_to_player("room", "alice", &update);
+        assert_eq!(received(&mut table_queue), 1);
+        assert_eq!(received(&mut phone_queue), 1);
+
+        registry.set_room(table, "room");
+        registry.send_to_player("room", "alice", &update);
+        assert_eq!(received(&mut table_queue), 1);
+        assert_eq!(received(&mut phone_queue), 0);
+    }
+
+    #[test]
+    fn stops_routing_to_a_socket_that_continues_as_another_player() {
+        let registry = registry(3, SessionLimitPolicy::Reject);
+        let (connection, mut queue) = connect_player(&registry, "alice");
+        let update = serde_json::json!({ "event": "spin" });
+
+        registry.bind_player(connection, "bob");
+        registry.send_to_player("room", "alice", &update);
+        assert_eq!(received(&mut queue), 0);
+        registry.send_to_player("room", "bob", &update);
+        assert_eq!(received(&mut queue), 1);
+    }
+}
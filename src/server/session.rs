@@ -0,0 +1,237 @@
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Number of game updates kept per player for replay after a reconnect.
+const UPDATE_HISTORY_SIZE: usize = 256;
+
+/// Room membership and recent game updates of a player, kept across reconnects.
+struct PlayerSession {
+    room_id: String,
+    /// Connection currently driving the session, `None` while the player is disconnected.
+    attached: Option<Uuid>,
+    /// Connection that last detached, used to tell a stale expiry apart from a new one.
+    detached_by: Option<Uuid>,
+    next_seq: u64,
+    history: VecDeque<(u64, Value)>,
+}
+
+/// What a resuming connection needs to pick up where the previous one left off.
+pub struct ResumedSession {
+    pub room_id: String,
+    pub last_seq: u64,
+    /// Updates with a sequence number greater than the one the client last saw.
+    pub missed_updates: Vec<(u64, Value)>,
+    /// `true` if some missed updates have already been dropped from the history.
+    pub history_truncated: bool,
+    /// Connection the session was detached by before the resume, for `cancel_resume`.
+    detached_by: Option<Uuid>,
+}
+
+#[derive(Default)]
+pub struct SessionStore {
+    sessions: Mutex<HashMap<String, PlayerSession>>,
+}
+
+impl SessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that the player joined a room from the given connection, replacing any
+    /// previous session of that player.
+    pub fn join(&self, player_id: &str, room_id: &str, connection_id: Uuid) {
+        self.sessions.lock().unwrap().insert(
+            player_id.to_string(),
+            PlayerSession {
+                room_id: room_id.to_string(),
+                attached: Some(connection_id),
+                detached_by: None,
+                next_seq: 1,
+                history: VecDeque::new(),
+            },
+        );
+    }
+
+    /// Forgets the session of a player who left their room for good.
+    pub fn leave(&self, player_id: &str) {
+        self.sessions.lock().unwrap().remove(player_id);
+    }
+
+    /// Assigns the next sequence number to a game update and keeps it for replay.
+    pub fn record_update(&self, player_id: &str, state: &Value) -> u64 {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(player_id) else {
+            return 0;
+        };
+
+        let seq = session.next_seq;
+        session.next_seq += 1;
+        if session.history.len() == UPDATE_HISTORY_SIZE {
+            session.history.pop_front();
+        }
+        session.history.push_back((seq, state.clone()));
+        seq
+    }
+
+    /// Marks the session as disconnected. Returns `false` if the connection no longer owns
+    /// the session, e.g. because the player already resumed on another socket.
+    pub fn detach(&self, player_id: &str, connection_id: Uuid) -> bool {
+        let mut sessions = self.sessions.lock().unwrap();
+        match sessions.get_mut(player_id) {
+            Some(session) if session.attached == Some(connection_id) => {
+                session.attached = None;
+                session.detached_by = Some(connection_id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Reattaches a session to a new connection and collects the updates sent after
+    /// `last_seq`.
+    pub fn resume(
+        &self,
+        player_id: &str,
+        connection_id: Uuid,
+        last_seq: u64,
+    ) -> Option<ResumedSession> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(player_id)?;
+
+        let detached_by = session.detached_by.take();
+        session.attached = Some(connection_id);
+
+        let missed_updates: Vec<(u64, Value)> = session
+            .history
+            .iter()
+            .filter(|(seq, _)| *seq > last_seq)
+            .cloned()
+            .collect();
+        let history_truncated = session
+            .history
+            .front()
+            .is_some_and(|(oldest, _)| *oldest > last_seq + 1);
+
+        Some(ResumedSession {
+            room_id: session.room_id.clone(),
+            last_seq: session.next_seq - 1,
+            missed_updates,
+            history_truncated,
+            detached_by,
+        })
+    }
+
+    /// Undoes a resume the connection could not complete, detaching the session again so
+    /// that the pending expiry of the previous connection still applies.
+    pub fn cancel_resume(&self, player_id: &str, connection_id: Uuid, resumed: ResumedSession) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(session) = sessions.get_mut(player_id) {
+            if session.attached == Some(connection_id) {
+                session.attached = None;
+                session.detached_by = resumed.detached_by;
+            }
+        }
+    }
+
+    /// Drops the session if it is still detached by the given connection, returning the
+    /// room the player should be removed from.
+    pub fn expire(&self, player_id: &str, connection_id: Uuid) -> Option<String> {
+        let mut sessions = self.sessions.lock().unwrap();
+        match sessions.get(player_id) {
+            Some(session)
+                if session.attached.is_none() && session.detached_by == Some(connection_id) =>
+            {
+                sessions.remove(player_id).map(|session| session.room_id)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn resume_replays_the_missed_updates() {
+        let store = SessionStore::new();
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        store.join("alice", "room", first);
+        for n in 1..=3 {
+            assert_eq!(store.record_update("alice", &json!({ "n": n })), n);
+        }
+        assert!(store.detach("alice", first));
+
+        let resumed = store.resume("alice", second, 1).unwrap();
+        assert_eq!(resumed.room_id, "room");
+        assert_eq!(resumed.last_seq, 3);
+        assert_eq!(
+            resumed.missed_updates,
+            vec![(2, json!({ "n": 2 })), (3, json!({ "n": 3 }))]
+        );
+        assert!(!resumed.history_truncated);
+    }
+
+    #[test]
+    fn resume_reports_updates_dropped_from_the_history() {
+        let store = SessionStore::new();
+        store.join("alice", "room", Uuid::new_v4());
+        for n in 0..UPDATE_HISTORY_SIZE + 2 {
+            store.record_update("alice", &json!(n));
+        }
+
+        let resumed = store.resume("alice", Uuid::new_v4(), 0).unwrap();
+        assert_eq!(resumed.missed_updates.len(), UPDATE_HISTORY_SIZE);
+        assert_eq!(resumed.missed_updates[0].0, 3);
+        assert!(resumed.history_truncated);
+    }
+
+    #[test]
+    fn resume_without_a_session_fails() {
+        let store = SessionStore::new();
+        assert!(store.resume("alice", Uuid::new_v4(), 0).is_none());
+        assert_eq!(store.record_update("alice", &json!({})), 0);
+    }
+
+    #[test]
+    fn expire_drops_a_session_still_detached_by_the_connection() {
+        let store = SessionStore::new();
+        let connection = Uuid::new_v4();
+        store.join("alice", "room", connection);
+
+        assert_eq!(store.expire("alice", connection), None);
+        assert!(store.detach("alice", connection));
+        assert_eq!(store.expire("alice", connection), Some("room".to_string()));
+        assert!(store.resume("alice", Uuid::new_v4(), 0).is_none());
+    }
+
+    #[test]
+    fn expire_leaves_a_resumed_session_alone() {
+        let store = SessionStore::new();
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        store.join("alice", "room", first);
+        store.detach("alice", first);
+        store.resume("alice", second, 0).unwrap();
+
+        assert_eq!(store.expire("alice", first), None);
+        assert!(!store.detach("alice", first));
+        assert!(store.detach("alice", second));
+    }
+
+    #[test]
+    fn cancelled_resume_leaves_the_session_to_its_pending_expiry() {
+        let store = SessionStore::new();
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        store.join("alice", "room", first);
+        store.detach("alice", first);
+
+        let resumed = store.resume("alice", second, 0).unwrap();
+        store.cancel_resume("alice", second, resumed);
+
+        assert!(!store.detach("alice", second));
+        assert_eq!(store.expire("alice", first), Some("room".to_string()));
+    }
+}
@@ -0,0 +1,134 @@
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::server::{ClientHello, ResolvesServerCert};
+use tokio_rustls::rustls::sign::CertifiedKey;
+use tokio_rustls::rustls::{self, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+use crate::config::Config;
+
+#[derive(Error, Debug)]
+pub enum TlsError {
+    #[error("Both tls cert_path and key_path must be set")]
+    IncompleteConfig,
+    #[error("No certificates found in {0}")]
+    NoCertificates(String),
+    #[error("No private key found in {0}")]
+    NoPrivateKey(String),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("TLS error: {0}")]
+    Rustls(#[from] rustls::Error),
+}
+
+/// Serves the certificate and key found at the configured paths, picking up new files
+/// without a restart so that renewed certificates take effect on the next handshake.
+#[derive(Debug)]
+pub struct CertificateStore {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+    /// Modification times of the certificate and key that are currently served.
+    loaded: Mutex<(Option<SystemTime>, Option<SystemTime>)>,
+}
+
+impl CertificateStore {
+    pub fn load(cert_path: PathBuf, key_path: PathBuf) -> Result<Self, TlsError> {
+        let loaded = modification_times(&cert_path, &key_path);
+        let current = load_certified_key(&cert_path, &key_path)?;
+        Ok(CertificateStore {
+            cert_path,
+            key_path,
+            current: RwLock::new(Arc::new(current)),
+            loaded: Mutex::new(loaded),
+        })
+    }
+
+    /// Reloads the certificate and key if either file changed since they were last loaded.
+    /// A broken pair is reported and the previous certificate stays in use.
+    pub fn reload_if_changed(&self) {
+        let modified = modification_times(&self.cert_path, &self.key_path);
+        let mut loaded = self.loaded.lock().unwrap();
+        if *loaded == modified {
+            return;
+        }
+
+        match load_certified_key(&self.cert_path, &self.key_path) {
+            Ok(key) => {
+                *self.current.write().unwrap() = Arc::new(key);
+                *loaded = modified;
+                println!("Reloaded TLS certificate from {}", self.cert_path.display());
+            }
+            Err(e) => eprintln!("Error reloading TLS certificate: {}", e),
+        }
+    }
+}
+
+impl ResolvesServerCert for CertificateStore {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(Arc::clone(&self.current.read().unwrap()))
+    }
+}
+
+fn modification_times(
+    cert_path: &Path,
+    key_path: &Path,
+) -> (Option<SystemTime>, Option<SystemTime>) {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    (modified(cert_path), modified(key_path))
+}
+
+fn load_certified_key(cert_path: &Path, key_path: &Path) -> Result<CertifiedKey, TlsError> {
+    let mut reader = BufReader::new(File::open(cert_path)?);
+    let certs = rustls_pemfile::certs(&mut reader).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(TlsError::NoCertificates(cert_path.display().to_string()));
+    }
+
+    let mut reader = BufReader::new(File::open(key_path)?);
+    let key = rustls_pemfile::private_key(&mut reader)?
+        .ok_or_else(|| TlsError::NoPrivateKey(key_path.display().to_string()))?;
+    let signing_key = ring::sign::any_supported_type(&key)?;
+
+    Ok(CertifiedKey::new(certs, signing_key))
+}
+
+/// Builds the acceptor for `wss://` connections from the `[tls]` section, or returns `None`
+/// if TLS is not configured. The certificate files are checked for changes every
+/// `reload_interval_secs`.
+pub fn from_config(config: &Config) -> Result<Option<TlsAcceptor>, TlsError> {
+    let (cert_path, key_path) = match (&config.tls_cert_path, &config.tls_key_path) {
+        (Some(cert_path), Some(key_path)) => (cert_path, key_path),
+        (None, None) => return Ok(None),
+        _ => return Err(TlsError::IncompleteConfig),
+    };
+
+    let store = Arc::new(CertificateStore::load(
+        PathBuf::from(cert_path),
+        PathBuf::from(key_path),
+    )?);
+
+    let server_config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_cert_resolver(store.clone());
+
+    let reload_interval = Duration::from_secs(config.tls_reload_interval_secs);
+    if !reload_interval.is_zero() {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(reload_interval);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                store.reload_if_changed();
+            }
+        });
+    }
+
+    Ok(Some(TlsAcceptor::from(Arc::new(server_config))))
+}